@@ -1,7 +1,7 @@
 use criterion::measurement::WallTime;
 use criterion::{criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion};
 use rayon::prelude::*;
-use viper::primitives::commitment::Commitment;
+use viper::primitives::commitment::CommitmentMatrix;
 use viper::primitives::mkhs::Signature;
 use viper::system::aggregator::Aggregator;
 
@@ -17,17 +17,17 @@ fn aggregator(
     // Setup
     let utils::Setup { mkhs, clients, .. } = utils::Setup::build(clients, rows, cols, decimals);
 
-    let commitments: Vec<Vec<Commitment>> = clients
+    let commitments: Vec<CommitmentMatrix> = clients
         .par_iter()
         .map(|client| client.compute_commitments())
         .collect();
 
     // Computing signatures
-    let signatures: Vec<Vec<Signature>> = clients
+    let signatures: Vec<Signature> = clients
         .par_iter()
         .map(|client| {
             let fr_dataset = client.dataset.fr();
-            client.compute_signature(&mkhs, &fr_dataset)
+            client.compute_signature(&mkhs, &fr_dataset).unwrap()
         })
         .collect();
 
@@ -39,7 +39,7 @@ fn aggregator(
         ),
         |b| {
             b.iter(|| {
-                Aggregator::aggregate_commitments(&commitments);
+                let _ = Aggregator::aggregate_commitments(&commitments);
             })
         },
     );
@@ -52,7 +52,7 @@ fn aggregator(
         ),
         |b| {
             b.iter(|| {
-                Aggregator::aggregate_signatures(&mkhs, &signatures);
+                let _ = Aggregator::aggregate_signatures(&mkhs, &signatures);
             })
         },
     );
@@ -1,7 +1,7 @@
 use criterion::measurement::WallTime;
 use criterion::{criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion};
 use rayon::prelude::*;
-use viper::primitives::commitment::Commitment;
+use viper::primitives::commitment::CommitmentMatrix;
 use viper::system::aggregator::Aggregator;
 
 mod utils;
@@ -9,13 +9,13 @@ mod utils;
 fn aggregator_open(rows: usize, cols: usize, decimals: u32, group: &mut BenchmarkGroup<WallTime>) {
     let n_client = 1;
     let utils::Setup {
-        aggregated_secret,
+        aggregated_randomness,
         clients,
         ..
     } = utils::Setup::build(n_client, rows, cols, decimals);
 
     // Computing commitments
-    let commitments: Vec<Vec<Commitment>> = clients
+    let commitments: Vec<CommitmentMatrix> = clients
         .par_iter()
         .map(|client| client.compute_commitments())
         .collect();
@@ -30,7 +30,12 @@ fn aggregator_open(rows: usize, cols: usize, decimals: u32, group: &mut Benchmar
         ),
         |b| {
             b.iter(|| {
-                let _ = Aggregator::open_commitments(&aggregated_commitments, &aggregated_secret);
+                let _ = Aggregator::open_table(
+                    &aggregated_commitments,
+                    &aggregated_randomness,
+                    &clients[0].dataset.schema,
+                    n_client,
+                );
             })
         },
     );
@@ -34,7 +34,7 @@ fn client_computation(
         ),
         |b| {
             b.iter(|| {
-                clients[0].compute_signature(&mkhs, &bench_dataset);
+                clients[0].compute_signature(&mkhs, &bench_dataset).unwrap();
             })
         },
     );
@@ -1,10 +1,7 @@
-use ark_bls12_381::Fr;
 use criterion::measurement::WallTime;
 use criterion::{criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion};
-use num_bigint::BigInt;
-use num_traits::ToPrimitive;
 use rayon::prelude::*;
-use viper::primitives::commitment::Commitment;
+use viper::primitives::commitment::CommitmentMatrix;
 use viper::primitives::mkhs::Signature;
 use viper::system::client::Client;
 
@@ -19,37 +16,31 @@ fn client_verification(
     let n_client = 1;
     let utils::Setup {
         mkhs,
-        aggregated_secret,
+        aggregated_randomness,
         clients,
         pks,
     } = utils::Setup::build(n_client, rows, cols, decimals);
 
     // Computing commitments
-    let commitments: Vec<Vec<Commitment>> = clients
+    let commitments: Vec<CommitmentMatrix> = clients
         .par_iter()
         .map(|client| client.compute_commitments())
         .collect();
 
     // Computing signatures
-    let signatures: Vec<Vec<Signature>> = clients
+    let signatures: Vec<Signature> = clients
         .par_iter()
         .map(|client| {
             let fr_dataset = client.dataset.fr();
-            client.compute_signature(&mkhs, &fr_dataset)
+            client.compute_signature(&mkhs, &fr_dataset).unwrap()
         })
         .collect();
 
     // Aggregation
-    let aggregated_dataset = clients[0]
-        .dataset
-        .dataset
-        .iter()
-        .flatten()
-        .map(|e| e.clone())
-        .collect::<Vec<BigInt>>();
+    let aggregated_dataset = clients[0].dataset.dataset.clone();
 
     let aggregated_commitments = commitments[0].clone();
-    let aggregated_signatures = signatures[0].clone();
+    let aggregated_signature = signatures[0].clone();
 
     // Verifying commitments
     group.bench_function(
@@ -62,18 +53,15 @@ fn client_verification(
                 let _ = Client::verify_commitment(
                     &aggregated_commitments,
                     &aggregated_dataset,
-                    &aggregated_secret,
+                    &aggregated_randomness,
                 );
             })
         },
     );
 
     // Verifying signatures
-    let aggregated_data: Vec<Fr> = aggregated_dataset
-        .par_iter()
-        .map(|el| Fr::from(el.to_u64().unwrap()))
-        .collect();
-    let aggregated_data: Vec<Vec<Fr>> = aggregated_data.chunks(cols).map(|s| s.into()).collect();
+    let schema = &clients[0].dataset.schema;
+    let aggregated_data = clients[0].dataset.fr();
     group.bench_function(
         BenchmarkId::new(
             "verifying_signatures",
@@ -81,8 +69,13 @@ fn client_verification(
         ),
         |b| {
             b.iter(|| {
-                let _ =
-                    Client::verify_signature(&mkhs, &pks, &aggregated_data, &aggregated_signatures);
+                let _ = Client::verify_signature(
+                    &mkhs,
+                    &pks,
+                    schema,
+                    &aggregated_data,
+                    &aggregated_signature,
+                );
             })
         },
     );
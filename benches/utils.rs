@@ -1,9 +1,12 @@
+#![allow(dead_code)]
+
 use num_bigint::BigInt;
-use rand::Rng;
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator};
 use rayon::prelude::*;
 use std::collections::HashMap;
+use viper::primitives::ec::random_scalar;
 use viper::primitives::mkhs::{Mkhs, PK};
+use viper::system::aggregator::Aggregator;
 use viper::system::client::Client;
 use viper::util::dataset::Dataset;
 
@@ -35,18 +38,15 @@ impl Parameters {
 
 pub struct Setup {
     pub mkhs: Mkhs,
-    pub aggregated_secret: BigInt,
+    pub aggregated_randomness: BigInt,
     pub clients: Vec<Client>,
     pub pks: HashMap<u64, PK>,
 }
 
 impl Setup {
     pub fn build(n_client: usize, n_row: usize, n_col: usize, decimals: u32) -> Self {
-        let mkhs = Mkhs::setup(n_client, n_col);
-        let secrets: Vec<BigInt> = (0..n_client)
-            .map(|_| BigInt::from(rand::thread_rng().gen_range(1..=100)))
-            .collect();
-        let aggregated_secret: BigInt = secrets.iter().sum();
+        let mkhs = Mkhs::setup(n_col);
+        let secrets: Vec<BigInt> = (0..n_client).map(|_| random_scalar()).collect();
 
         let clients: Vec<Client> = (1..=n_client)
             .into_par_iter()
@@ -57,6 +57,12 @@ impl Setup {
             })
             .collect();
 
+        let randomness: Vec<BigInt> = clients
+            .par_iter()
+            .map(|client| client.randomness())
+            .collect();
+        let aggregated_randomness = Aggregator::unmask_randomness(&randomness);
+
         let pks: HashMap<u64, PK> = HashMap::from_par_iter(
             clients
                 .par_iter()
@@ -65,7 +71,7 @@ impl Setup {
 
         Self {
             mkhs,
-            aggregated_secret,
+            aggregated_randomness,
             clients,
             pks,
         }
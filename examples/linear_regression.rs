@@ -0,0 +1,78 @@
+use rand::Rng;
+use viper::fl::fedavg::{FedAvg, FixedPoint};
+
+const N_CLIENT: usize = 3;
+const N_SAMPLE: usize = 20;
+const N_ROUND: usize = 5;
+const LOCAL_EPOCHS: usize = 10;
+const LEARNING_RATE: f64 = 0.1;
+
+/// `y = w * x + b`, trained locally with full-batch gradient descent.
+#[derive(Debug, Clone, Copy, Default)]
+struct LinearRegression {
+    w: f64,
+    b: f64,
+}
+
+impl LinearRegression {
+    fn predict(&self, x: f64) -> f64 {
+        self.w * x + self.b
+    }
+
+    fn mse(&self, data: &[(f64, f64)]) -> f64 {
+        data.iter()
+            .map(|(x, y)| (self.predict(*x) - y).powi(2))
+            .sum::<f64>()
+            / data.len() as f64
+    }
+
+    /// Trains a local copy and returns the update `[dw, db]`.
+    fn local_update(&self, data: &[(f64, f64)]) -> Vec<f64> {
+        let mut model = *self;
+        for _ in 0..LOCAL_EPOCHS {
+            let (mut grad_w, mut grad_b) = (0.0, 0.0);
+            for (x, y) in data {
+                let error = model.predict(*x) - y;
+                grad_w += 2.0 * error * x / data.len() as f64;
+                grad_b += 2.0 * error / data.len() as f64;
+            }
+            model.w -= LEARNING_RATE * grad_w;
+            model.b -= LEARNING_RATE * grad_b;
+        }
+        vec![model.w - self.w, model.b - self.b]
+    }
+}
+
+fn main() {
+    // Every client holds noisy samples of y = 2x + 1 with x in [0, 1].
+    let mut rng = rand::thread_rng();
+    let datasets: Vec<Vec<(f64, f64)>> = (0..N_CLIENT)
+        .map(|_| {
+            (0..N_SAMPLE)
+                .map(|_| {
+                    let x: f64 = rng.gen();
+                    (x, 2.0 * x + 1.0 + rng.gen_range(-0.05..0.05))
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut fedavg = FedAvg::new(N_CLIENT, 2, FixedPoint::new(4, 5.0)).unwrap();
+    let mut model = LinearRegression::default();
+
+    for round in 1..=N_ROUND {
+        let updates: Vec<Vec<f64>> = datasets
+            .iter()
+            .map(|data| model.local_update(data))
+            .collect();
+        let mean = fedavg.round(&updates).unwrap();
+        model.w += mean[0];
+        model.b += mean[1];
+
+        let loss = datasets.iter().map(|data| model.mse(data)).sum::<f64>() / N_CLIENT as f64;
+        println!(
+            "Round {}: w = {:.4}, b = {:.4}, mse = {:.6}",
+            round, model.w, model.b, loss
+        );
+    }
+}
@@ -0,0 +1,25 @@
+use std::env;
+use std::net::TcpListener;
+use std::sync::Arc;
+use viper::primitives::mkhs::Mkhs;
+use viper::system::server::Server;
+use viper::util::schema::Schema;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 5 {
+        println!("Usage: {} <addr> <n_client> <n_col> <decimals>", args[0]);
+        return Ok(());
+    }
+    let n_client: usize = args[2].parse()?;
+    let n_col: usize = args[3].parse()?;
+    let decimals: u32 = args[4].parse()?;
+
+    let schema = Schema::build(n_col, decimals);
+    let mkhs = Mkhs::setup(schema.len());
+    let server = Arc::new(Server::new(mkhs, schema, n_client));
+
+    let listener = TcpListener::bind(&args[1])?;
+    println!("AGGREGATOR LISTENING ON {}...", listener.local_addr()?);
+    server.serve(listener)
+}
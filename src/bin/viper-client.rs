@@ -0,0 +1,21 @@
+use std::env;
+use viper::primitives::ec::random_scalar;
+use viper::system::daemon::run_client;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        println!("Usage: {} <addr> <id> <n_row>", args[0]);
+        return Ok(());
+    }
+    let id: u64 = args[2].parse()?;
+    let n_row: usize = args[3].parse()?;
+    let secret = random_scalar();
+
+    println!("CLIENT {} RUNNING ROUND...", id);
+    let table = run_client(&args[1], id, n_row, secret)?;
+
+    println!("RESULT VERIFIED. AGGREGATED TABLE:");
+    print!("{}", table);
+    Ok(())
+}
@@ -0,0 +1,406 @@
+use crate::primitives::dp::{Accountant, Mechanism};
+use crate::primitives::ec::random_scalar;
+use crate::primitives::mkhs::{Mkhs, PK};
+use crate::primitives::norm_proof::{Norm, NormBound};
+use crate::system::client::Client;
+use crate::system::masking::MaskingKey;
+use crate::system::session::{RoundResult, Session};
+use crate::util::dataset::Dataset;
+use crate::util::schema::{Column, Schema};
+use anyhow::anyhow;
+use ark_bls12_381::G1Projective;
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const ROUND_DURATION: Duration = Duration::from_secs(3600);
+
+/// Fixed-point encoding of model updates. Values are clipped to
+/// `[-clip, clip]` and shifted by `clip`, so every encoded value is a
+/// non-negative integer in `[0, 2 * clip * 10^decimals]`. A `margin` widens
+/// the encoded range by that many units on both sides, as headroom for noise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedPoint {
+    pub decimals: u32,
+    pub clip: f64,
+    pub margin: i64,
+}
+
+impl FixedPoint {
+    pub const fn new(decimals: u32, clip: f64) -> Self {
+        Self {
+            decimals,
+            clip,
+            margin: 0,
+        }
+    }
+
+    /// Leaves room for noise of up to `margin` encoded units, e.g.
+    /// [`Mechanism::margin`].
+    pub const fn with_margin(self, margin: i64) -> Self {
+        Self { margin, ..self }
+    }
+
+    fn scale(&self) -> f64 {
+        10f64.powi(self.decimals as i32)
+    }
+
+    /// Width of the encoded range of an update value, without the margin.
+    fn range(&self) -> i64 {
+        2 * (self.clip * self.scale()).round() as i64
+    }
+
+    fn offset(&self) -> i64 {
+        (self.clip * self.scale()).round() as i64 + self.margin
+    }
+
+    pub fn schema(&self, dim: usize) -> Schema {
+        let max = BigInt::from(2 * self.offset());
+        let columns = (0..dim)
+            .map(|i| {
+                Column::new(
+                    &format!("w_{}", i),
+                    self.decimals,
+                    BigInt::zero(),
+                    max.clone(),
+                )
+            })
+            .collect();
+        Schema { columns }
+    }
+
+    pub fn encode(&self, update: &[f64]) -> anyhow::Result<Vec<BigInt>> {
+        update
+            .iter()
+            .map(|el| {
+                if !el.is_finite() {
+                    return Err(anyhow!("Cannot encode non-finite value {}.", el));
+                }
+                let clipped = el.clamp(-self.clip, self.clip);
+                Ok(BigInt::from(
+                    (clipped * self.scale()).round() as i64 + self.offset(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Decodes the sum of `n_client` encoded updates into their average.
+    pub fn decode_mean(&self, sum: &[BigInt], n_client: usize) -> anyhow::Result<Vec<f64>> {
+        let offset = BigInt::from(self.offset()) * n_client;
+        sum.iter()
+            .map(|el| {
+                let shifted = (el - &offset)
+                    .to_f64()
+                    .ok_or_else(|| anyhow!("Cannot decode {}.", el))?;
+                Ok(shifted / self.scale() / n_client as f64)
+            })
+            .collect()
+    }
+}
+
+/// Federated averaging over verifiable aggregation: every call to
+/// [`FedAvg::round`] runs one [`Session`] round in which each client commits
+/// to and signs its encoded update, and returns the verified average.
+pub struct FedAvg {
+    mkhs: Mkhs,
+    codec: FixedPoint,
+    clients: Vec<Client>,
+    masking_keys: Vec<MaskingKey>,
+    pks: HashMap<u64, PK>,
+    session: Session,
+    noise: Option<Mechanism>,
+    accountant: Accountant,
+    l2_bound: Option<f64>,
+}
+
+impl FedAvg {
+    pub fn new(n_client: usize, dim: usize, codec: FixedPoint) -> anyhow::Result<Self> {
+        let schema = codec.schema(dim);
+        schema.check_clients(n_client).map_err(|_| {
+            anyhow!(
+                "{} clients with clip {} and {} decimals overflow the opening bound.",
+                n_client,
+                codec.clip,
+                codec.decimals
+            )
+        })?;
+
+        let mkhs = Mkhs::setup(dim);
+        let clients: Vec<Client> = (1..=n_client as u64)
+            .into_par_iter()
+            .map(|id| {
+                let secret = random_scalar();
+                let dataset = Dataset {
+                    schema: schema.clone(),
+                    dataset: vec![],
+                };
+                Client::new(id, mkhs.generate_keys(id), dataset, secret)
+            })
+            .collect();
+        let masking_keys = (0..n_client).map(|_| MaskingKey::generate()).collect();
+        let pks: HashMap<u64, PK> = clients
+            .iter()
+            .map(|el| (el.id, el.key_pair.pk.clone()))
+            .collect();
+        let session = Session::new(mkhs.clone(), schema, pks.clone(), ROUND_DURATION);
+
+        Ok(Self {
+            mkhs,
+            codec,
+            clients,
+            masking_keys,
+            pks,
+            session,
+            noise: None,
+            accountant: Accountant::new(),
+            l2_bound: None,
+        })
+    }
+
+    /// Clips every update to an L2 norm of `bound` and makes clients prove
+    /// the bound on their encoded update. Noise is added after clipping, so
+    /// the proven bound also covers noise of up to the codec margin in every
+    /// coordinate.
+    pub fn with_l2_bound(self, bound: f64) -> anyhow::Result<Self> {
+        if !bound.is_finite() || bound < 0.0 {
+            return Err(anyhow!("Invalid L2 bound {}.", bound));
+        }
+        // Rounding moves each coordinate by at most half a unit.
+        let dim = self.mkhs.t as f64;
+        let slack = (0.5 + self.codec.margin as f64) * dim.sqrt();
+        let encoded = (bound * self.codec.scale() + slack).ceil() as i64;
+        let norm_bound = NormBound::new(
+            Norm::L2,
+            BigInt::from(self.codec.offset()),
+            BigInt::from(encoded),
+        );
+        Ok(Self {
+            session: self.session.with_norm_bound(norm_bound)?,
+            l2_bound: Some(bound),
+            ..self
+        })
+    }
+
+    /// Makes every client add `mechanism` noise to its encoded update. The
+    /// codec margin must cover [`Mechanism::margin`].
+    pub fn with_noise(mut self, mechanism: Mechanism) -> anyhow::Result<Self> {
+        mechanism.check()?;
+        if self.codec.margin < mechanism.margin() {
+            return Err(anyhow!(
+                "Codec margin {} is below the noise margin {}.",
+                self.codec.margin,
+                mechanism.margin()
+            ));
+        }
+        self.noise = Some(mechanism);
+        Ok(self)
+    }
+
+    /// The privacy loss of the rounds run so far, if noise is enabled.
+    pub fn epsilon(&self, delta: f64) -> Option<anyhow::Result<f64>> {
+        self.noise.map(|_| self.accountant.epsilon(delta))
+    }
+
+    pub fn codec(&self) -> &FixedPoint {
+        &self.codec
+    }
+
+    /// Aggregates one update per client and returns their average.
+    pub fn round(&mut self, updates: &[Vec<f64>]) -> anyhow::Result<Vec<f64>> {
+        if updates.len() != self.clients.len() {
+            return Err(anyhow!(
+                "Expected {} updates, got {}.",
+                self.clients.len(),
+                updates.len()
+            ));
+        }
+
+        let datasets = updates
+            .iter()
+            .zip(self.clients.iter())
+            .map(|(update, client)| {
+                let update = match self.l2_bound {
+                    Some(bound) => clip_l2(update, bound),
+                    None => update.clone(),
+                };
+                let mut dataset =
+                    Dataset::with_schema(&client.dataset.schema, &[self.codec.encode(&update)?])?;
+                if let Some(noise) = &self.noise {
+                    dataset.add_noise(noise)?;
+                }
+                Ok(dataset)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let ids: Vec<u64> = self.clients.iter().map(|el| el.id).collect();
+        let round = self.session.start_round(&ids)?;
+        let result = match self.run_round(round, &ids, datasets) {
+            Ok(result) => result,
+            Err(e) => {
+                // Leave the session ready for the next round.
+                self.session.abort_round()?;
+                return Err(e);
+            }
+        };
+        Client::verify_result(&self.mkhs, &self.pks, &result.result, &result.randomness)?;
+        if let Some(noise) = &self.noise {
+            // Replacing one client's update moves each coordinate by at most
+            // the full encoded range.
+            let (range, dim) = (self.codec.range() as f64, updates[0].len() as f64);
+            self.accountant
+                .record(noise, self.clients.len(), range * dim, range * dim.sqrt())?;
+        }
+        self.codec
+            .decode_mean(&result.result.table.rows[0], self.clients.len())
+    }
+
+    /// Submits every client's dataset to the open `round` and closes it.
+    fn run_round(
+        &mut self,
+        round: u64,
+        ids: &[u64],
+        datasets: Vec<Dataset>,
+    ) -> anyhow::Result<RoundResult> {
+        let peers: HashMap<u64, G1Projective> = ids
+            .iter()
+            .zip(self.masking_keys.iter())
+            .map(|(id, key)| (*id, key.public))
+            .collect();
+
+        let submissions: Vec<_> = self
+            .clients
+            .par_iter_mut()
+            .zip(self.masking_keys.par_iter())
+            .zip(datasets)
+            .map(|((client, key), dataset)| {
+                client.dataset = dataset;
+                client.round = round;
+                client.submission(&self.mkhs, key, &peers, self.session.norm_bound())
+            })
+            .collect::<anyhow::Result<_>>()?;
+        for submission in submissions {
+            self.session.submit(submission)?;
+        }
+        self.session.close_round(&[])
+    }
+}
+
+fn clip_l2(update: &[f64], bound: f64) -> Vec<f64> {
+    let norm = update.iter().map(|el| el * el).sum::<f64>().sqrt();
+    if norm <= bound {
+        return update.to_vec();
+    }
+    update.iter().map(|el| el * bound / norm).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_point() {
+        let codec = FixedPoint::new(2, 1.0);
+        let encoded = codec.encode(&[0.5, -0.25, 3.0, -7.0]).unwrap();
+        assert_eq!(
+            encoded,
+            vec![
+                BigInt::from(150),
+                BigInt::from(75),
+                BigInt::from(200),
+                BigInt::from(0)
+            ]
+        );
+        assert!(codec.schema(4).check(&[encoded]).is_ok());
+        assert!(codec.encode(&[f64::NAN]).is_err());
+
+        let sum = vec![BigInt::from(150 + 75), BigInt::from(100 + 100)];
+        assert_eq!(codec.decode_mean(&sum, 2).unwrap(), vec![0.125, 0.0]);
+
+        let codec = codec.with_margin(10);
+        let encoded = codec.encode(&[0.5, -7.0]).unwrap();
+        assert_eq!(encoded, vec![BigInt::from(160), BigInt::from(10)]);
+        assert_eq!(codec.schema(2).columns[0].max, BigInt::from(220));
+        let sum = vec![BigInt::from(160 + 85), BigInt::from(10 + 110)];
+        assert_eq!(codec.decode_mean(&sum, 2).unwrap(), vec![0.125, -0.5]);
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert!(FedAvg::new(2, 1, FixedPoint::new(11, 10.0)).is_err());
+    }
+
+    #[test]
+    fn test_fedavg_round() {
+        let mut fedavg = FedAvg::new(2, 2, FixedPoint::new(2, 1.0)).unwrap();
+        let mean = fedavg.round(&[vec![0.5, -0.5], vec![0.25, 0.1]]).unwrap();
+        assert_eq!(mean, vec![0.375, -0.2]);
+        assert!(fedavg.round(&[vec![0.5, -0.5]]).is_err());
+        assert!(fedavg.round(&[vec![0.5], vec![0.5]]).is_err());
+        assert!(fedavg.session.current().is_none());
+        assert!(fedavg.epsilon(1e-5).is_none());
+    }
+
+    #[test]
+    fn test_fedavg_failed_round() {
+        let mut fedavg = FedAvg::new(2, 1, FixedPoint::new(1, 1.0)).unwrap();
+        // A key the session does not know makes the submission tag invalid.
+        let key_pair = fedavg.clients[0].key_pair.clone();
+        fedavg.clients[0].key_pair = fedavg.mkhs.generate_keys(1);
+        assert!(fedavg.round(&[vec![0.5], vec![0.1]]).is_err());
+        assert!(fedavg.session.current().is_none());
+
+        fedavg.clients[0].key_pair = key_pair;
+        assert_eq!(fedavg.round(&[vec![0.5], vec![0.1]]).unwrap(), vec![0.3]);
+    }
+
+    #[test]
+    fn test_fedavg_l2_bound() {
+        let mut fedavg = FedAvg::new(2, 2, FixedPoint::new(1, 5.0))
+            .unwrap()
+            .with_l2_bound(1.0)
+            .unwrap();
+        // The second update has norm 5 and is scaled down to norm 1.
+        let mean = fedavg.round(&[vec![0.0, 0.0], vec![3.0, 4.0]]).unwrap();
+        assert_eq!(mean, vec![0.3, 0.4]);
+        assert!(FedAvg::new(2, 2, FixedPoint::new(4, 5.0))
+            .unwrap()
+            .with_l2_bound(-1.0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_fedavg_noise() {
+        let noise = Mechanism::Skellam { mu: 100.0 };
+        let codec = FixedPoint::new(1, 5.0).with_margin(noise.margin());
+        let mut fedavg = FedAvg::new(2, 1, codec).unwrap().with_noise(noise).unwrap();
+        let mean = fedavg.round(&[vec![1.0], vec![2.0]]).unwrap();
+        assert!((mean[0] - 1.5).abs() < 5.0);
+        assert!(fedavg.epsilon(1e-5).unwrap().unwrap().is_finite());
+        assert!(FedAvg::new(2, 1, codec)
+            .unwrap()
+            .with_noise(Mechanism::DiscreteGaussian { sigma: 0.0 })
+            .is_err());
+        // Without headroom noise would have to be clamped.
+        assert!(FedAvg::new(2, 1, FixedPoint::new(1, 5.0))
+            .unwrap()
+            .with_noise(noise)
+            .is_err());
+    }
+
+    #[test]
+    fn test_fedavg_noise_l2_bound() {
+        let noise = Mechanism::Skellam { mu: 100.0 };
+        let codec = FixedPoint::new(1, 5.0).with_margin(noise.margin());
+        let mut fedavg = FedAvg::new(2, 2, codec)
+            .unwrap()
+            .with_noise(noise)
+            .unwrap()
+            .with_l2_bound(1.0)
+            .unwrap();
+        let mean = fedavg.round(&[vec![0.0, 0.0], vec![3.0, 4.0]]).unwrap();
+        assert!((mean[0] - 0.3).abs() < 5.0 && (mean[1] - 0.4).abs() < 5.0);
+        assert!(fedavg.epsilon(1e-5).unwrap().unwrap().is_finite());
+    }
+}
@@ -1,3 +1,4 @@
+pub mod fl;
 pub mod primitives;
 pub mod system;
 pub mod util;
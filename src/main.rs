@@ -1,32 +1,450 @@
-use ark_bls12_381::Fr;
+use anyhow::anyhow;
+use ark_bls12_381::G1Projective;
+use bigdecimal::BigDecimal;
 use num_bigint::BigInt;
-use num_traits::ToPrimitive;
-use rand::Rng;
 use rayon::prelude::*;
 use std::collections::HashMap;
-use viper::primitives::commitment::Commitment;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use viper::primitives::commitment::{Commitment, CommitmentMatrix};
+use viper::primitives::consistency_proof::ConsistencyProof;
+use viper::primitives::ec::random_scalar;
 use viper::primitives::mkhs::{Mkhs, Signature, PK};
-use viper::system::aggregator::Aggregator;
+use viper::primitives::range_proof::RangeProof;
+use viper::system::aggregator::{AggregationResult, Aggregator};
 use viper::system::client::Client;
+use viper::system::masking::MaskingKey;
+use viper::util::codec::{Decode, Encode};
 use viper::util::dataset::Dataset;
+use viper::util::keystore::Keystore;
+use viper::util::schema::Schema;
 
-fn main() {
+const USAGE: &str = "Usage:
+  viper setup <params> <n_col> <decimals>
+  viper keygen <params> <id> <keystore> <public>
+  viper commit <params> <keystore> <dataset.csv> <round> <out> <public>...
+  viper sign <params> <keystore> <dataset.csv> <round> <out>
+  viper aggregate <params> <round> <out> (<commitments> <signature>)...
+  viper open <params> <aggregate> <result>
+  viper verify <params> <result> <public>...
+  viper demo";
+
+/// Public parameters shared by every party.
+struct Params {
+    mkhs: Mkhs,
+    schema: Schema,
+}
+
+/// What a client publishes about itself.
+struct PublicKey {
+    id: u64,
+    pk: PK,
+    masking_key: G1Projective,
+}
+
+/// Output of `commit`, sent to the aggregator.
+struct Commitments {
+    id: u64,
+    commitments: CommitmentMatrix,
+    randomness_commitment: Commitment,
+    range_proofs: Vec<RangeProof>,
+    masked_randomness: BigInt,
+}
+
+/// Output of `sign`, sent to the aggregator.
+struct DatasetSignature {
+    id: u64,
+    signature: Signature,
+    consistency_proof: ConsistencyProof,
+}
+
+/// Aggregated but not yet opened values, written by `aggregate`.
+struct Aggregate {
+    commitments: CommitmentMatrix,
+    randomness_commitment: Commitment,
+    signature: Signature,
+    randomness: BigInt,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.get(1..).unwrap_or_default() {
+        ["setup", params, n_col, decimals] => setup(params, n_col.parse()?, decimals.parse()?),
+        ["keygen", params, id, key, public] => keygen(params, id.parse()?, key, public),
+        ["commit", params, key, dataset, round, out, publics @ ..] if !publics.is_empty() => {
+            commit(params, key, dataset, round.parse()?, out, publics)
+        }
+        ["sign", params, key, dataset, round, out] => {
+            sign(params, key, dataset, round.parse()?, out)
+        }
+        ["aggregate", params, round, out, inputs @ ..]
+            if !inputs.is_empty() && inputs.len() % 2 == 0 =>
+        {
+            aggregate(params, round.parse()?, out, inputs)
+        }
+        ["open", params, aggregate, result] => open(params, aggregate, result),
+        ["verify", params, result, publics @ ..] if !publics.is_empty() => {
+            verify(params, result, publics)
+        }
+        ["demo"] => {
+            demo();
+            Ok(())
+        }
+        _ => Err(anyhow!("{}", USAGE)),
+    }
+}
+
+fn setup(path: &str, n_col: usize, decimals: u32) -> anyhow::Result<()> {
+    let schema = Schema::build(n_col, decimals);
+    let params = Params {
+        mkhs: Mkhs::setup(schema.len()),
+        schema,
+    };
+    write(path, &params)
+}
+
+/// Generates a new key version of client `id` in `keystore`, encrypted under
+/// the passphrase in `VIPER_PASSPHRASE`.
+fn keygen(params: &str, id: u64, keystore: &str, public: &str) -> anyhow::Result<()> {
+    let params: Params = read(params)?;
+    let (_, keys) = Keystore::open(keystore)?.rotate(&params.mkhs, id, &passphrase()?)?;
+    write(
+        public,
+        &PublicKey {
+            id,
+            pk: keys.key_pair.pk,
+            masking_key: keys.masking_key.public,
+        },
+    )
+}
+
+fn commit(
+    params: &str,
+    key: &str,
+    dataset: &str,
+    round: u64,
+    out: &str,
+    publics: &[&str],
+) -> anyhow::Result<()> {
+    let params: Params = read(params)?;
+    let (client, masking_key) = load_client(&params, key, dataset, round)?;
+    let peers: HashMap<u64, G1Projective> = read_publics(publics)?
+        .into_iter()
+        .map(|el| (el.id, el.masking_key))
+        .collect();
+    if !peers.contains_key(&client.id) {
+        return Err(anyhow!("Public keys do not include client {}.", client.id));
+    }
+    write(
+        out,
+        &Commitments {
+            id: client.id,
+            commitments: client.compute_commitments(),
+            randomness_commitment: client.commit_randomness(),
+            range_proofs: client.compute_range_proofs()?,
+            masked_randomness: client.masked_randomness(&masking_key, &peers),
+        },
+    )
+}
+
+fn sign(params: &str, key: &str, dataset: &str, round: u64, out: &str) -> anyhow::Result<()> {
+    let params: Params = read(params)?;
+    let (client, _) = load_client(&params, key, dataset, round)?;
+    let (signature, consistency_proof) = client.compute_signature_with_proof(&params.mkhs)?;
+    write(
+        out,
+        &DatasetSignature {
+            id: client.id,
+            signature,
+            consistency_proof,
+        },
+    )
+}
+
+fn aggregate(params: &str, round: u64, out: &str, inputs: &[&str]) -> anyhow::Result<()> {
+    let params: Params = read(params)?;
+    let uploads = inputs
+        .chunks(2)
+        .map(|pair| {
+            let commitments: Commitments = read(pair[0])?;
+            let signature: DatasetSignature = read(pair[1])?;
+            if commitments.id != signature.id {
+                return Err(anyhow!(
+                    "{} and {} belong to different clients.",
+                    pair[0],
+                    pair[1]
+                ));
+            }
+            params.mkhs.verify_label(&signature.signature, round)?;
+            Aggregator::verify_range_proofs(
+                &params.schema,
+                &commitments.commitments,
+                &commitments.randomness_commitment,
+                &commitments.range_proofs,
+            )?;
+            Aggregator::verify_consistency_proof(
+                &params.mkhs,
+                commitments.id,
+                &params.schema,
+                &commitments.commitments,
+                &commitments.randomness_commitment,
+                &signature.signature,
+                &signature.consistency_proof,
+            )
+            .map_err(|e| anyhow!("Client {}: {}", commitments.id, e))?;
+            Ok((commitments, signature))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let (commitments, signatures): (Vec<Commitments>, Vec<DatasetSignature>) =
+        uploads.into_iter().unzip();
+    let randomness = Aggregator::unmask_randomness(
+        &commitments
+            .iter()
+            .map(|el| el.masked_randomness.clone())
+            .collect::<Vec<_>>(),
+    );
+    let randomness_commitment: Commitment = commitments
+        .iter()
+        .map(|el| el.randomness_commitment.clone())
+        .sum();
+    randomness_commitment.open_randomness(&randomness)?;
+    let aggregate = Aggregate {
+        commitments: Aggregator::aggregate_commitments(
+            &commitments
+                .into_iter()
+                .map(|el| el.commitments)
+                .collect::<Vec<_>>(),
+        )?,
+        randomness_commitment,
+        signature: Aggregator::aggregate_signatures(
+            &params.mkhs,
+            &signatures
+                .into_iter()
+                .map(|el| el.signature)
+                .collect::<Vec<_>>(),
+        )?,
+        randomness,
+    };
+    write(out, &aggregate)
+}
+
+fn open(params: &str, aggregate: &str, result: &str) -> anyhow::Result<()> {
+    let params: Params = read(params)?;
+    let aggregate: Aggregate = read(aggregate)?;
+    let n_client = aggregate.signature.participants().len();
+    let table = Aggregator::open_table(
+        &aggregate.commitments,
+        &aggregate.randomness,
+        &params.schema,
+        n_client,
+    )?;
+    let opening_proofs = Aggregator::prove_openings(&table.rows, &aggregate.randomness)?;
+    print!("{}", table);
+    write(
+        result,
+        &AggregationResult {
+            commitments: aggregate.commitments,
+            randomness_commitment: aggregate.randomness_commitment,
+            signature: aggregate.signature,
+            table,
+            opening_proofs,
+        },
+    )
+}
+
+fn verify(params: &str, result: &str, publics: &[&str]) -> anyhow::Result<()> {
+    let params: Params = read(params)?;
+    let result: AggregationResult = read(result)?;
+    let pks: HashMap<u64, PK> = read_publics(publics)?
+        .into_iter()
+        .map(|el| (el.id, el.pk))
+        .collect();
+    Client::audit_result(&params.mkhs, &pks, &result)?;
+    println!("RESULT VERIFIED. AGGREGATED TABLE:");
+    print!("{}", result.table);
+    Ok(())
+}
+
+/// Loads the latest keys of the single client in `keystore`.
+fn load_client(
+    params: &Params,
+    keystore: &str,
+    dataset: &str,
+    round: u64,
+) -> anyhow::Result<(Client, MaskingKey)> {
+    let keystore = Keystore::open(keystore)?;
+    let versions: Vec<(u64, u32)> = keystore.versions()?.into_iter().collect();
+    let [(id, version)] = versions.as_slice() else {
+        return Err(anyhow!(
+            "Keystore holds the keys of {} clients, expected 1.",
+            versions.len()
+        ));
+    };
+    let keys = keystore.load(&params.mkhs, *id, *version, &passphrase()?)?;
+    let dataset = read_csv(&params.schema, dataset)?;
+    let mut client = Client::new(*id, keys.key_pair, dataset, keys.secret);
+    client.round = round;
+    Ok((client, keys.masking_key))
+}
+
+fn passphrase() -> anyhow::Result<String> {
+    env::var("VIPER_PASSPHRASE")
+        .map_err(|_| anyhow!("Set VIPER_PASSPHRASE to the keystore passphrase."))
+}
+
+/// Reads one row per line of comma-separated decimal values.
+fn read_csv(schema: &Schema, path: &str) -> anyhow::Result<Dataset> {
+    let rows = fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            if values.len() != schema.len() {
+                return Err(anyhow!(
+                    "Row has {} values, schema has {} columns.",
+                    values.len(),
+                    schema.len()
+                ));
+            }
+            values
+                .iter()
+                .zip(schema.columns.iter())
+                .map(|(value, column)| column.encode(&BigDecimal::from_str(value)?))
+                .collect()
+        })
+        .collect::<anyhow::Result<Vec<Vec<BigInt>>>>()?;
+    Dataset::with_schema(schema, &rows)
+}
+
+fn read_publics(paths: &[&str]) -> anyhow::Result<Vec<PublicKey>> {
+    paths.iter().map(|path| read(path)).collect()
+}
+
+fn read<T: Decode>(path: &str) -> anyhow::Result<T> {
+    fs::read(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| T::from_bytes(&bytes))
+        .map_err(|e| anyhow!("{}: {}", path, e))
+}
+
+fn write<T: Encode>(path: &str, value: &T) -> anyhow::Result<()> {
+    Ok(fs::write(path, value.to_bytes())?)
+}
+
+impl Encode for Params {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.mkhs.encode(buf);
+        self.schema.encode(buf);
+    }
+}
+
+impl Decode for Params {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            mkhs: Mkhs::decode(buf)?,
+            schema: Schema::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for PublicKey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.id.encode(buf);
+        self.pk.encode(buf);
+        self.masking_key.encode(buf);
+    }
+}
+
+impl Decode for PublicKey {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: u64::decode(buf)?,
+            pk: PK::decode(buf)?,
+            masking_key: G1Projective::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Commitments {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.id.encode(buf);
+        self.commitments.encode(buf);
+        self.randomness_commitment.encode(buf);
+        self.range_proofs.encode(buf);
+        self.masked_randomness.encode(buf);
+    }
+}
+
+impl Decode for Commitments {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: u64::decode(buf)?,
+            commitments: CommitmentMatrix::decode(buf)?,
+            randomness_commitment: Commitment::decode(buf)?,
+            range_proofs: Vec::decode(buf)?,
+            masked_randomness: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for DatasetSignature {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.id.encode(buf);
+        self.signature.encode(buf);
+        self.consistency_proof.encode(buf);
+    }
+}
+
+impl Decode for DatasetSignature {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: u64::decode(buf)?,
+            signature: Signature::decode(buf)?,
+            consistency_proof: ConsistencyProof::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Aggregate {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.commitments.encode(buf);
+        self.randomness_commitment.encode(buf);
+        self.signature.encode(buf);
+        self.randomness.encode(buf);
+    }
+}
+
+impl Decode for Aggregate {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            commitments: CommitmentMatrix::decode(buf)?,
+            randomness_commitment: Commitment::decode(buf)?,
+            signature: Signature::decode(buf)?,
+            randomness: BigInt::decode(buf)?,
+        })
+    }
+}
+
+/// Runs every role in a single process on sampled data.
+fn demo() {
     let n_col = 2;
     let n_row = 3;
 
     let n_client = 2;
-    let mkhs = Mkhs::setup(n_client, n_col);
+    let schema = Schema::build(n_col, 4);
+    let mkhs = Mkhs::setup(schema.len());
 
-    let secrets: Vec<BigInt> = (0..n_client)
-        .map(|_| BigInt::from(rand::thread_rng().gen_range(1..=100)))
-        .collect();
+    let secrets: Vec<BigInt> = (0..n_client).map(|_| random_scalar()).collect();
 
     // Training phase
     println!("TRAINING...");
     let clients: Vec<Client> = (1..=n_client)
         .into_par_iter()
         .map(|id| {
-            let dataset = Dataset::build(n_col, n_row, 4);
+            let dataset = Dataset::sample(&schema, n_row);
             let key_pair = mkhs.generate_keys(id as u64);
             Client::new(id as u64, key_pair, dataset, secrets[id - 1].clone())
         })
@@ -40,52 +458,66 @@ fn main() {
 
     // Commitments and Signatures
     println!("COMPUTING COMMITMENTS...");
-    let commitments: Vec<Vec<Commitment>> = clients
+    let commitments: Vec<CommitmentMatrix> = clients
         .par_iter()
         .map(|client| client.compute_commitments())
         .collect();
+    let randomness_commitments: Vec<Commitment> = clients
+        .par_iter()
+        .map(|client| client.commit_randomness())
+        .collect();
+    println!("PROVING RANGES...");
+    clients
+        .par_iter()
+        .zip(commitments.par_iter())
+        .zip(randomness_commitments.par_iter())
+        .try_for_each(|((client, c), s)| {
+            let proofs = client.compute_range_proofs()?;
+            Aggregator::verify_range_proofs(&schema, c, s, &proofs)
+        })
+        .unwrap();
     println!("COMPUTING SIGNATURES...");
-    let signatures: Vec<Vec<Signature>> = clients
+    let signatures: Vec<Signature> = clients
         .par_iter()
         .map(|client| {
             let fr_dataset = client.dataset.fr();
-            client.compute_signature(&mkhs, &fr_dataset)
+            client.compute_signature(&mkhs, &fr_dataset).unwrap()
         })
         .collect();
 
-    // Aggregator
-    println!("AGGREGATING COMMITMENTS...");
-    let aggregated_commitments = Aggregator::aggregate_commitments(&commitments);
-    println!("AGGREGATING SIGNATURES...");
-    let aggregated_signatures = Aggregator::aggregate_signatures(&mkhs, &signatures);
-
-    // Clients secret
-    let aggregated_secret = secrets.iter().sum();
+    // Clients randomness
+    println!("MASKING RANDOMNESS...");
+    let masking_keys: Vec<MaskingKey> = (0..n_client).map(|_| MaskingKey::generate()).collect();
+    let peers: HashMap<u64, G1Projective> = clients
+        .iter()
+        .zip(masking_keys.iter())
+        .map(|(client, key)| (client.id, key.public))
+        .collect();
+    let masked_randomness: Vec<BigInt> = clients
+        .par_iter()
+        .zip(masking_keys.par_iter())
+        .map(|(client, key)| client.masked_randomness(key, &peers))
+        .collect();
+    let aggregated_randomness = Aggregator::unmask_randomness(&masked_randomness);
 
-    // Aggregator Open
-    println!("OPENING COMMITMENTS...");
-    let aggregated_dataset =
-        Aggregator::open_commitments(&aggregated_commitments, &aggregated_secret).unwrap();
+    // Aggregator
+    println!("AGGREGATING AND OPENING...");
+    let result = Aggregator::aggregate(
+        &mkhs,
+        &schema,
+        &commitments,
+        &signatures,
+        &randomness_commitments,
+        &aggregated_randomness,
+    )
+    .unwrap();
 
     // Clients' verification
-    println!("VERIFYING COMMITMENTS...");
-    let commitment_check = Client::verify_commitment(
-        &aggregated_commitments,
-        &aggregated_dataset,
-        &aggregated_secret,
-    );
-
-    println!("Commitment check: {:?}", commitment_check);
-
-    let aggregated_data: Vec<Fr> = aggregated_dataset
-        .par_iter()
-        .map(|el| Fr::from(el.to_u64().unwrap()))
-        .collect();
-    let aggregated_data: Vec<Vec<Fr>> = aggregated_data.chunks(n_col).map(|s| s.into()).collect();
+    println!("VERIFYING RESULT...");
+    let check = Client::verify_result(&mkhs, &pks, &result, &aggregated_randomness);
 
-    println!("VERIFYING SIGNATURES...");
-    let signature_check =
-        Client::verify_signature(&mkhs, &pks, &aggregated_data, &aggregated_signatures);
+    println!("Result check: {:?}", check);
 
-    println!("Signature check: {:?}", signature_check);
+    println!("AGGREGATED TABLE:");
+    print!("{}", result.table);
 }
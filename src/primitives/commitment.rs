@@ -1,6 +1,9 @@
-use crate::primitives::ec::Point;
+use crate::primitives::ec::{Point, DEFAULTEC};
 use anyhow::anyhow;
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
+use num_traits::One;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use std::iter::Sum;
 use std::ops::Add;
 
@@ -22,18 +25,119 @@ impl Commitment {
         Self { c }
     }
 
-    pub fn commit(w: &BigInt, r: &BigInt) -> Self {
+    /// `C = w * G + r * h`, where `h` is the generator of the cell from
+    /// [`generator`]. A client uses the same `r` for every cell and
+    /// publishes it once as `r * H` with [`Commitment::commit_randomness`];
+    /// the generators differing per cell is what keeps equal values apart.
+    pub fn commit(w: &BigInt, r: &BigInt, h: &Point) -> Self {
         let g_w = Point::default() * w;
-        let h_r = Point::default() * r;
+        let h_r = h.clone() * r;
         Self { c: &g_w + &h_r }
     }
 
-    pub fn open(&self, w: &BigInt, r: &BigInt) -> anyhow::Result<()> {
-        if self.c != Self::commit(w, r).c {
+    pub fn open(&self, w: &BigInt, r: &BigInt, h: &Point) -> anyhow::Result<()> {
+        if self.c != Self::commit(w, r, h).c {
             return Err(anyhow!("Open failed."));
         }
         Ok(())
     }
+
+    pub fn commit_randomness(r: &BigInt) -> Self {
+        Self { c: Point::h() * r }
+    }
+
+    pub fn open_randomness(&self, r: &BigInt) -> anyhow::Result<()> {
+        if self.c != Self::commit_randomness(r).c {
+            return Err(anyhow!("Randomness open failed."));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CommitmentMatrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<Commitment>,
+}
+
+impl CommitmentMatrix {
+    pub fn new(rows: usize, cols: usize, data: Vec<Commitment>) -> anyhow::Result<Self> {
+        if rows * cols != data.len() {
+            return Err(anyhow!(
+                "Cannot shape {} commitments into {}x{}.",
+                data.len(),
+                rows,
+                cols
+            ));
+        }
+        Ok(Self { rows, cols, data })
+    }
+
+    pub const fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn check_shape(&self, rows: usize, cols: usize) -> anyhow::Result<()> {
+        if self.shape() != (rows, cols) {
+            return Err(anyhow!(
+                "Shape mismatch: expected {}x{}, found {}x{}.",
+                rows,
+                cols,
+                self.rows,
+                self.cols
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<&Commitment> {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+        self.data.get(row * self.cols + col)
+    }
+
+    /// The generators of every cell, in the order of `data`.
+    pub fn generators(&self) -> Vec<Point> {
+        generators(self.rows, self.cols)
+    }
+
+    pub fn row(&self, row: usize) -> Option<&[Commitment]> {
+        if row >= self.rows {
+            return None;
+        }
+        Some(&self.data[row * self.cols..(row + 1) * self.cols])
+    }
+}
+
+/// The generator `H_ij` of cell `(row, col)`, hashed to the curve so that
+/// no discrete log relation between cells, `G` and `H` is known.
+pub fn generator(row: usize, col: usize) -> Point {
+    let label = [
+        b"viper-cell-generator".as_slice(),
+        &(row as u64).to_le_bytes(),
+        &(col as u64).to_le_bytes(),
+    ]
+    .concat();
+    Point::hash_to_curve(&label)
+}
+
+pub fn generators(rows: usize, cols: usize) -> Vec<Point> {
+    (0..rows * cols)
+        .into_par_iter()
+        .map(|i| generator(i / cols, i % cols))
+        .collect()
+}
+
+/// The randomness a client commits with in `round`, derived from its seed.
+pub fn derive_randomness(seed: &BigInt, round: u64) -> BigInt {
+    let seed = seed.modpow(&BigInt::one(), &DEFAULTEC.n);
+    let digest = Sha256::new()
+        .chain_update(seed.to_signed_bytes_le())
+        .chain_update(round.to_le_bytes())
+        .finalize();
+    BigInt::from_bytes_le(Sign::Plus, &digest).modpow(&BigInt::one(), &DEFAULTEC.n)
 }
 
 impl Add for &Commitment {
@@ -64,45 +168,102 @@ mod tests {
     fn test_commitment() {
         let w = BigInt::from(5);
         let r = BigInt::from(7);
-        let commitment = Commitment::commit(&w, &r);
-        assert!(commitment.open(&w, &r).is_ok());
+        let h = generator(0, 0);
+        let commitment = Commitment::commit(&w, &r, &h);
+        assert!(commitment.open(&w, &r, &h).is_ok());
+        assert!(commitment.open(&w, &r, &generator(0, 1)).is_err());
     }
 
     #[test]
     fn test_invalid_commitment() {
         let w = BigInt::from(5);
         let r = BigInt::from(7);
-        let commitment = Commitment::commit(&w, &r);
-        assert!(commitment.open(&BigInt::from(10), &r).is_err());
+        let h = generator(0, 0);
+        let commitment = Commitment::commit(&w, &r, &h);
+        assert!(commitment.open(&BigInt::from(10), &r, &h).is_err());
     }
 
     #[test]
     fn test_add_commitment() {
+        let h = generator(1, 2);
         let w1 = BigInt::from(5);
         let r1 = BigInt::from(7);
-        let c1 = Commitment::commit(&w1, &r1);
+        let c1 = Commitment::commit(&w1, &r1, &h);
 
         let w2 = BigInt::from(123);
         let r2 = BigInt::from(321);
-        let c2 = Commitment::commit(&w2, &r2);
+        let c2 = Commitment::commit(&w2, &r2, &h);
 
         let sum = &c1 + &c2;
 
-        assert!(sum.open(&(w1 + w2), &(r1 + r2)).is_ok());
+        assert!(sum.open(&(w1 + w2), &(r1 + r2), &h).is_ok());
+    }
+
+    #[test]
+    fn test_commitment_matrix() {
+        let hs = generators(3, 2);
+        let data: Vec<Commitment> = (0..6)
+            .map(|i| Commitment::commit(&BigInt::from(i), &BigInt::from(1), &hs[i]))
+            .collect();
+
+        assert!(CommitmentMatrix::new(4, 2, data.clone()).is_err());
+
+        let matrix = CommitmentMatrix::new(3, 2, data.clone()).unwrap();
+        assert!(matrix.check_shape(3, 2).is_ok());
+        assert!(matrix.check_shape(2, 3).is_err());
+        assert_eq!(matrix.get(1, 1), Some(&data[3]));
+        assert_eq!(matrix.row(2), Some(&data[4..6]));
+        assert!(matrix.get(3, 0).is_none());
+        assert_eq!(matrix.generators(), hs);
+    }
+
+    #[test]
+    fn test_randomness_commitment() {
+        let r1 = BigInt::from(7);
+        let r2 = BigInt::from(9);
+        let sum = &Commitment::commit_randomness(&r1) + &Commitment::commit_randomness(&r2);
+
+        assert!(sum.open_randomness(&BigInt::from(16)).is_ok());
+        assert!(sum.open_randomness(&BigInt::from(17)).is_err());
+        assert_ne!(
+            Commitment::commit_randomness(&r1),
+            Commitment::commit(&BigInt::from(0), &r1, &generator(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_generators() {
+        let hs = generators(2, 3);
+        assert_eq!(hs.len(), 6);
+        assert_eq!(hs[5], generator(1, 2));
+        assert_ne!(generator(0, 1), generator(1, 0));
+        assert!(hs
+            .iter()
+            .all(|h| *h != Point::h() && *h != Point::default()));
+    }
+
+    #[test]
+    fn test_derive_randomness() {
+        let seed = BigInt::from(11);
+        let r = derive_randomness(&seed, 0);
+        assert_eq!(r, derive_randomness(&(&seed + &DEFAULTEC.n), 0));
+        assert_ne!(r, derive_randomness(&BigInt::from(12), 0));
+        assert_ne!(r, derive_randomness(&seed, 1));
     }
 
     #[test]
     fn test_invalid_add_commitment() {
+        let h = generator(0, 0);
         let w1 = BigInt::from(5);
         let r1 = BigInt::from(7);
-        let c1 = Commitment::commit(&w1, &r1);
+        let c1 = Commitment::commit(&w1, &r1, &h);
 
         let w2 = BigInt::from(123);
         let r2 = BigInt::from(321);
-        let c2 = Commitment::commit(&w2, &r2);
+        let c2 = Commitment::commit(&w2, &r2, &h);
 
         let sum = &c1 + &c2;
 
-        assert!(sum.open(&(w1 - w2), &(r1 + r2)).is_err());
+        assert!(sum.open(&(w1 - w2), &(r1 + r2), &h).is_err());
     }
 }
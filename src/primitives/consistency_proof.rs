@@ -0,0 +1,337 @@
+use crate::primitives::commitment::{generators, Commitment, CommitmentMatrix};
+use crate::primitives::ec::{random_scalar, to_scalar, Point};
+use crate::primitives::mkhs::{Mkhs, Signature};
+use crate::primitives::range_proof::{challenge, mul, reduce};
+use anyhow::anyhow;
+use ark_bls12_381::{Fr, G1Projective};
+use ark_std::UniformRand;
+use num_bigint::BigInt;
+use rand::thread_rng;
+use rayon::prelude::*;
+
+/// Proof that the dataset signed in a client's MKHS dataset signature is the
+/// dataset committed in `C_k = w_k * G + r * H_k`, `S = r * H`.
+///
+/// The signature's `C = s * g1 + sum_k w_k * H'_k` and the commitments are
+/// both in G1, so a single Schnorr proof with one response per cell shows
+/// both are over the same values.
+///
+/// The challenge covers the id of the client, so a proof only verifies
+/// against a signature of the client that submitted it. `context` is the
+/// one the dataset was signed under, see [`Mkhs::sign_dataset`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConsistencyProof {
+    pub(crate) e: BigInt,
+    pub(crate) z_s: Fr,
+    pub(crate) z_w: Vec<BigInt>,
+    pub(crate) z_r: BigInt,
+}
+
+impl ConsistencyProof {
+    pub fn prove(
+        mkhs: &Mkhs,
+        client_id: u64,
+        context: &[u8],
+        s: &Fr,
+        rows: &[Vec<BigInt>],
+        randomness: &BigInt,
+    ) -> anyhow::Result<Self> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != cols) {
+            return Err(anyhow!("Dataset rows must all have the same length."));
+        }
+        let signed_generators = mkhs.dataset_generators(context, rows.len(), cols)?;
+        let generators = generators(rows.len(), cols);
+        let cells: Vec<&BigInt> = rows.iter().flatten().collect();
+
+        let big_c = Point::from(signed_commitment(mkhs, &signed_generators, s, &cells));
+        let commitments: Vec<Commitment> = cells
+            .par_iter()
+            .zip(&generators)
+            .map(|(w, h)| Commitment::commit(w, randomness, h))
+            .collect();
+        let randomness_commitment = Commitment::commit_randomness(randomness);
+
+        let k_s = Fr::rand(&mut thread_rng());
+        let k_w: Vec<BigInt> = cells.iter().map(|_| random_scalar()).collect();
+        let k_r = random_scalar();
+        let t_c = Point::from(signed_commitment(
+            mkhs,
+            &signed_generators,
+            &k_s,
+            &k_w.iter().collect::<Vec<_>>(),
+        ));
+        let t: Vec<Point> = k_w
+            .par_iter()
+            .zip(&generators)
+            .map(|(k, h)| &mul(&Point::default(), k) + &mul(h, &k_r))
+            .collect();
+        let u = mul(&Point::h(), &k_r);
+
+        let e = challenge(
+            &label(client_id),
+            &transcript(&big_c, &t_c, &commitments, &randomness_commitment, &t, &u),
+        );
+        Ok(Self {
+            z_s: k_s + to_scalar(&e) * s,
+            z_w: k_w
+                .iter()
+                .zip(cells)
+                .map(|(k, w)| reduce(&(k + &e * w)))
+                .collect(),
+            z_r: reduce(&(k_r + &e * randomness)),
+            e,
+        })
+    }
+
+    pub fn verify(
+        &self,
+        mkhs: &Mkhs,
+        client_id: u64,
+        context: &[u8],
+        signature: &Signature,
+        commitments: &CommitmentMatrix,
+        randomness_commitment: &Commitment,
+    ) -> anyhow::Result<()> {
+        let [lam] = signature.lams.as_slice() else {
+            return Err(anyhow!("Expected a single-client signature."));
+        };
+        if lam.client_id != client_id {
+            return Err(anyhow!(
+                "Signature is from client {}, expected {}.",
+                lam.client_id,
+                client_id
+            ));
+        }
+        let n = commitments.data.len();
+        if self.z_w.len() != n {
+            return Err(anyhow!(
+                "Consistency proof does not match the dataset shape."
+            ));
+        }
+        let signed_generators =
+            mkhs.dataset_generators(context, commitments.rows, commitments.cols)?;
+        let generators = commitments.generators();
+
+        let big_c = Point::from(lam.big_c);
+        let t_c = Point::from(
+            signed_commitment(
+                mkhs,
+                &signed_generators,
+                &self.z_s,
+                &self.z_w.iter().collect::<Vec<_>>(),
+            ) - lam.big_c * to_scalar(&self.e),
+        );
+        let neg_e = -&self.e;
+        let t: Vec<Point> = (0..n)
+            .into_par_iter()
+            .map(|i| {
+                &(&mul(&Point::default(), &self.z_w[i]) + &mul(&generators[i], &self.z_r))
+                    + &mul(&commitments.data[i].c, &neg_e)
+            })
+            .collect();
+        let u = &mul(&Point::h(), &self.z_r) + &mul(&randomness_commitment.c, &neg_e);
+
+        let e = challenge(
+            &label(client_id),
+            &transcript(
+                &big_c,
+                &t_c,
+                &commitments.data,
+                randomness_commitment,
+                &t,
+                &u,
+            ),
+        );
+        if e != self.e {
+            return Err(anyhow!(
+                "Signature and commitments are over different values."
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `s * g1 + sum_k w_k * H'_k`, the `C` term of an MKHS signature over the
+/// generators `H'_k`.
+fn signed_commitment(
+    mkhs: &Mkhs,
+    signed_generators: &[G1Projective],
+    s: &Fr,
+    values: &[&BigInt],
+) -> G1Projective {
+    mkhs.g1 * s
+        + values
+            .par_iter()
+            .zip(signed_generators)
+            .map(|(w, h)| *h * to_scalar(w))
+            .sum::<G1Projective>()
+}
+
+fn label(client_id: u64) -> Vec<u8> {
+    [b"consistency".as_slice(), &client_id.to_le_bytes()].concat()
+}
+
+fn transcript<'a>(
+    big_c: &'a Point,
+    t_c: &'a Point,
+    commitments: &'a [Commitment],
+    randomness_commitment: &'a Commitment,
+    t: &'a [Point],
+    u: &'a Point,
+) -> Vec<&'a Point> {
+    [big_c, t_c]
+        .into_iter()
+        .chain(commitments.iter().map(|el| &el.c))
+        .chain([&randomness_commitment.c])
+        .chain(t)
+        .chain([u])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::dataset::to_fr as dataset_fr;
+
+    const CONTEXT: &[u8] = b"schema";
+
+    fn commit(rows: &[Vec<BigInt>], r: &BigInt) -> (CommitmentMatrix, Commitment) {
+        let cols = rows[0].len();
+        let data = rows
+            .iter()
+            .flatten()
+            .zip(generators(rows.len(), cols))
+            .map(|(w, h)| Commitment::commit(w, r, &h))
+            .collect();
+        (
+            CommitmentMatrix::new(rows.len(), cols, data).unwrap(),
+            Commitment::commit_randomness(r),
+        )
+    }
+
+    #[test]
+    fn test_consistency_proof() {
+        let mkhs = Mkhs::setup(2);
+        let key_pair = mkhs.generate_keys(1);
+        let rows = vec![
+            vec![BigInt::from(7), BigInt::from(1000)],
+            vec![BigInt::from(3), BigInt::from(5)],
+        ];
+        let r = BigInt::from(3);
+        let (commitments, randomness_commitment) = commit(&rows, &r);
+
+        let (signature, s) = mkhs
+            .sign_dataset_with_randomness(&key_pair.sk, 0, CONTEXT, &dataset_fr(&rows))
+            .unwrap();
+        let proof = ConsistencyProof::prove(&mkhs, 1, CONTEXT, &s, &rows, &r).unwrap();
+        assert!(proof
+            .verify(
+                &mkhs,
+                1,
+                CONTEXT,
+                &signature,
+                &commitments,
+                &randomness_commitment
+            )
+            .is_ok());
+
+        // Signing one dataset and committing to another is caught, even if
+        // it only swaps rows.
+        let other = vec![rows[1].clone(), rows[0].clone()];
+        let (other_signature, other_s) = mkhs
+            .sign_dataset_with_randomness(&key_pair.sk, 0, CONTEXT, &dataset_fr(&other))
+            .unwrap();
+        assert!(proof
+            .verify(
+                &mkhs,
+                1,
+                CONTEXT,
+                &other_signature,
+                &commitments,
+                &randomness_commitment
+            )
+            .is_err());
+        let forged = ConsistencyProof::prove(&mkhs, 1, CONTEXT, &other_s, &rows, &r).unwrap();
+        assert!(forged
+            .verify(
+                &mkhs,
+                1,
+                CONTEXT,
+                &other_signature,
+                &commitments,
+                &randomness_commitment
+            )
+            .is_err());
+
+        // The proof is bound to the submitting client.
+        assert!(proof
+            .verify(
+                &mkhs,
+                2,
+                CONTEXT,
+                &signature,
+                &commitments,
+                &randomness_commitment
+            )
+            .is_err());
+        let other_key_pair = mkhs.generate_keys(2);
+        let (stolen, stolen_s) = mkhs
+            .sign_dataset_with_randomness(&other_key_pair.sk, 0, CONTEXT, &dataset_fr(&rows))
+            .unwrap();
+        let proof_as_1 = ConsistencyProof::prove(&mkhs, 1, CONTEXT, &stolen_s, &rows, &r).unwrap();
+        assert!(proof_as_1
+            .verify(
+                &mkhs,
+                1,
+                CONTEXT,
+                &stolen,
+                &commitments,
+                &randomness_commitment
+            )
+            .is_err());
+
+        let mut tampered = proof.clone();
+        tampered.z_w[0] += 1;
+        assert!(tampered
+            .verify(
+                &mkhs,
+                1,
+                CONTEXT,
+                &signature,
+                &commitments,
+                &randomness_commitment
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_dataset_shape() {
+        let mkhs = Mkhs::setup(2);
+        let r = BigInt::from(3);
+        let s = Fr::from(1);
+
+        let long = vec![vec![BigInt::from(1); 3]];
+        assert!(ConsistencyProof::prove(&mkhs, 1, CONTEXT, &s, &long, &r).is_err());
+        let ragged = vec![vec![BigInt::from(1); 2], vec![BigInt::from(1)]];
+        assert!(ConsistencyProof::prove(&mkhs, 1, CONTEXT, &s, &ragged, &r).is_err());
+
+        let rows = vec![vec![BigInt::from(1); 2]; 2];
+        let proof = ConsistencyProof::prove(&mkhs, 1, CONTEXT, &s, &rows, &r).unwrap();
+        let key_pair = mkhs.generate_keys(1);
+        let signature = mkhs
+            .sign_dataset(&key_pair.sk, 0, CONTEXT, &dataset_fr(&rows[..1]))
+            .unwrap();
+        let (commitments, randomness_commitment) = commit(&rows[..1], &r);
+        assert!(proof
+            .verify(
+                &mkhs,
+                1,
+                CONTEXT,
+                &signature,
+                &commitments,
+                &randomness_commitment
+            )
+            .is_err());
+    }
+}
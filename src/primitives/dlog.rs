@@ -0,0 +1,115 @@
+use crate::primitives::ec::Point;
+use anyhow::anyhow;
+use ark_bls12_381::{Fr, G1Affine, G1Projective};
+use ark_ec::{CurveGroup, Group};
+use ark_ff::{PrimeField, Zero};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Largest value [`DlogTable`] can recover: opening `2^40` takes a table of
+/// `2^20` baby steps and as many giant steps.
+pub const MAX_VALUE: u64 = 1 << 40;
+
+/// Giant steps normalized to affine coordinates at once.
+const BATCH: u64 = 256;
+
+/// Baby-step giant-step table for discrete logs in G1 bounded by `max`.
+/// Building it costs `sqrt(max)` additions, and every lookup at most as
+/// many again, so one table is built per opening and shared by every cell.
+pub struct DlogTable {
+    step: u64,
+    max: u64,
+    baby_steps: HashMap<u64, u64>,
+}
+
+impl DlogTable {
+    pub fn new(max: &BigInt) -> anyhow::Result<Self> {
+        let max = max
+            .to_u64()
+            .filter(|el| *el <= MAX_VALUE)
+            .ok_or_else(|| anyhow!("Cannot open values above {}, got {}.", MAX_VALUE, max))?;
+        let step = (max + 1).isqrt().max(1);
+        let mut points = Vec::with_capacity(step as usize);
+        let mut point = G1Projective::zero();
+        for _ in 0..step {
+            points.push(point);
+            point += G1Projective::generator();
+        }
+        let baby_steps = G1Projective::normalize_batch(&points)
+            .par_iter()
+            .enumerate()
+            .map(|(i, el)| (key(el), i as u64))
+            .collect();
+        Ok(Self {
+            step,
+            max,
+            baby_steps,
+        })
+    }
+
+    /// Finds `w` in `[0, max]` with `target = w * G`.
+    pub fn solve(&self, target: &Point, max: &BigInt) -> anyhow::Result<BigInt> {
+        let max = match max.to_u64() {
+            Some(max) if max <= self.max => max,
+            _ => return Err(anyhow!("Value bound {} exceeds the table's.", max)),
+        };
+        let giant_step = -(G1Projective::generator() * Fr::from(self.step));
+        let n_giant = max / self.step + 1;
+        let mut current = target.0;
+        for start in (0..n_giant).step_by(BATCH as usize) {
+            let end = (start + BATCH).min(n_giant);
+            let batch: Vec<G1Projective> = (start..end)
+                .map(|_| {
+                    let point = current;
+                    current += giant_step;
+                    point
+                })
+                .collect();
+            for (j, point) in (start..end).zip(G1Projective::normalize_batch(&batch)) {
+                if let Some(i) = self.baby_steps.get(&key(&point)) {
+                    let w = j * self.step + i;
+                    if w <= max && G1Projective::generator() * Fr::from(w) == target.0 {
+                        return Ok(BigInt::from(w));
+                    }
+                }
+            }
+        }
+        Err(anyhow!("No value in [0, {}] opens the commitment.", max))
+    }
+}
+
+/// The low 64 bits of the x coordinate. Collisions only cost a failed
+/// check, as every candidate is checked against the target.
+fn key(point: &G1Affine) -> u64 {
+    point.x.into_bigint().as_ref()[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dlog_table() {
+        let max = BigInt::from(10_000);
+        let table = DlogTable::new(&max).unwrap();
+        for w in [0u64, 1, 99, 100, 101, 5_000, 10_000] {
+            let target = Point::default() * &BigInt::from(w);
+            assert_eq!(table.solve(&target, &max).unwrap(), BigInt::from(w));
+        }
+
+        let target = Point::default() * &BigInt::from(10_001);
+        assert!(table.solve(&target, &max).is_err());
+        assert!(table.solve(&Point::h(), &max).is_err());
+        assert!(table.solve(&target, &BigInt::from(20_000)).is_err());
+
+        // Values within a smaller bound are found with fewer giant steps.
+        let target = Point::default() * &BigInt::from(42);
+        assert_eq!(
+            table.solve(&target, &BigInt::from(50)).unwrap(),
+            BigInt::from(42)
+        );
+        assert!(DlogTable::new(&BigInt::from(MAX_VALUE + 1)).is_err());
+    }
+}
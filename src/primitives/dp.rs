@@ -0,0 +1,221 @@
+use anyhow::anyhow;
+use rand::{thread_rng, Rng};
+use rand_distr::{Distribution, Poisson};
+
+/// Integer noise added by every client before committing. The parameters are
+/// per client, so the aggregate of `n` clients carries `n` times the variance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mechanism {
+    DiscreteGaussian {
+        sigma: f64,
+    },
+    /// Difference of two Poisson(`mu`) variables, with variance `2 * mu`.
+    Skellam {
+        mu: f64,
+    },
+}
+
+impl Mechanism {
+    pub fn check(&self) -> anyhow::Result<()> {
+        let param = match self {
+            Self::DiscreteGaussian { sigma } => sigma,
+            Self::Skellam { mu } => mu,
+        };
+        if !param.is_finite() || *param <= 0.0 {
+            return Err(anyhow!("Invalid noise parameter {}.", param));
+        }
+        Ok(())
+    }
+
+    pub fn variance(&self) -> f64 {
+        match self {
+            Self::DiscreteGaussian { sigma } => sigma * sigma,
+            Self::Skellam { mu } => 2.0 * mu,
+        }
+    }
+
+    /// Bound on the magnitude of a sample, exceeded with probability below
+    /// `2 * e^-50`: ten standard deviations for the discrete Gaussian, which
+    /// is sub-Gaussian, and Bernstein's inequality for the Skellam.
+    pub fn margin(&self) -> i64 {
+        (10.0 * self.variance().sqrt()).ceil() as i64 + 34
+    }
+
+    pub fn sample(&self) -> i64 {
+        match self {
+            Self::DiscreteGaussian { sigma } => discrete_gaussian(*sigma),
+            Self::Skellam { mu } => {
+                let poisson = Poisson::new(*mu).unwrap();
+                let mut rng = thread_rng();
+                poisson.sample(&mut rng) as i64 - poisson.sample(&mut rng) as i64
+            }
+        }
+    }
+
+    /// Rényi DP of the aggregate of `n_client` noisy contributions at `order`,
+    /// for a change of one contribution bounded by `l1` and `l2`.
+    ///
+    /// The sum of discrete Gaussians is treated as a discrete Gaussian of the
+    /// summed variance, which is tight for `sigma` above 1. The Skellam bound
+    /// is the one of Agarwal et al., "The Skellam Mechanism for Differentially
+    /// Private Federated Learning" (2021), valid for integer orders.
+    fn rdp(&self, order: f64, n_client: usize, l1: f64, l2: f64) -> f64 {
+        let n = n_client as f64;
+        match self {
+            Self::DiscreteGaussian { sigma } => order * l2 * l2 / (2.0 * n * sigma * sigma),
+            Self::Skellam { mu } => {
+                let mu = n * mu;
+                order * l2 * l2 / (4.0 * mu)
+                    + f64::min(
+                        ((2.0 * order - 1.0) * l2 * l2 + 6.0 * l1) / (16.0 * mu * mu),
+                        3.0 * l1 / (4.0 * mu),
+                    )
+            }
+        }
+    }
+}
+
+/// Canonne, Kamath and Steinke, "The Discrete Gaussian for Differential
+/// Privacy" (2020), Algorithm 3, with floating-point Bernoulli trials.
+fn discrete_gaussian(sigma: f64) -> i64 {
+    let mut rng = thread_rng();
+    let t = sigma.floor() + 1.0;
+    loop {
+        let u = rng.gen_range(0..t as i64);
+        if !rng.gen_bool((-(u as f64) / t).exp()) {
+            continue;
+        }
+        let mut v = 0;
+        while rng.gen_bool((-1f64).exp()) {
+            v += 1;
+        }
+        let x = u + t as i64 * v;
+        let negative = rng.gen_bool(0.5);
+        if negative && x == 0 {
+            continue;
+        }
+        let z = if negative { -x } else { x };
+        let bias = (z.abs() as f64 - sigma * sigma / t).powi(2) / (2.0 * sigma * sigma);
+        if rng.gen_bool((-bias).exp()) {
+            return z;
+        }
+    }
+}
+
+/// Tracks the privacy loss of repeated noisy aggregations through Rényi DP
+/// composition over a fixed set of integer orders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accountant {
+    orders: Vec<f64>,
+    rdp: Vec<f64>,
+    rounds: usize,
+}
+
+impl Default for Accountant {
+    fn default() -> Self {
+        let orders: Vec<f64> = (2..=256).map(f64::from).collect();
+        Self {
+            rdp: vec![0.0; orders.len()],
+            orders,
+            rounds: 0,
+        }
+    }
+}
+
+impl Accountant {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub const fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Records one aggregation round of `n_client` contributions with the
+    /// given L1 and L2 sensitivities (in encoded units).
+    pub fn record(
+        &mut self,
+        mechanism: &Mechanism,
+        n_client: usize,
+        l1: f64,
+        l2: f64,
+    ) -> anyhow::Result<()> {
+        mechanism.check()?;
+        if n_client == 0 {
+            return Err(anyhow!("A round needs at least one client."));
+        }
+        for (rdp, order) in self.rdp.iter_mut().zip(self.orders.iter()) {
+            *rdp += mechanism.rdp(*order, n_client, l1, l2);
+        }
+        self.rounds += 1;
+        Ok(())
+    }
+
+    /// The smallest epsilon such that all recorded rounds are
+    /// (epsilon, `delta`)-DP.
+    pub fn epsilon(&self, delta: f64) -> anyhow::Result<f64> {
+        if !(0.0..1.0).contains(&delta) || delta == 0.0 {
+            return Err(anyhow!("Delta must be in (0, 1)."));
+        }
+        Ok(self
+            .orders
+            .iter()
+            .zip(self.rdp.iter())
+            .map(|(order, rdp)| rdp + (1.0 / delta).ln() / (order - 1.0))
+            .fold(f64::INFINITY, f64::min))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_variance() {
+        for mechanism in [
+            Mechanism::DiscreteGaussian { sigma: 4.0 },
+            Mechanism::Skellam { mu: 8.0 },
+        ] {
+            let samples: Vec<f64> = (0..20000).map(|_| mechanism.sample() as f64).collect();
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let variance =
+                samples.iter().map(|el| (el - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+
+            assert!(mean.abs() < 0.2, "mean {}", mean);
+            assert!(
+                (variance / mechanism.variance() - 1.0).abs() < 0.1,
+                "variance {}",
+                variance
+            );
+        }
+    }
+
+    #[test]
+    fn test_accountant() {
+        let mechanism = Mechanism::DiscreteGaussian { sigma: 10.0 };
+        let mut accountant = Accountant::new();
+        accountant.record(&mechanism, 4, 1.0, 1.0).unwrap();
+        let one = accountant.epsilon(1e-5).unwrap();
+        accountant.record(&mechanism, 4, 1.0, 1.0).unwrap();
+        let two = accountant.epsilon(1e-5).unwrap();
+        assert_eq!(accountant.rounds(), 2);
+        assert!(one < two);
+
+        let mut noisier = Accountant::new();
+        noisier
+            .record(&Mechanism::DiscreteGaussian { sigma: 20.0 }, 4, 1.0, 1.0)
+            .unwrap();
+        assert!(noisier.epsilon(1e-5).unwrap() < one);
+
+        let mut skellam = Accountant::new();
+        skellam
+            .record(&Mechanism::Skellam { mu: 50.0 }, 4, 1.0, 1.0)
+            .unwrap();
+        assert!(skellam.epsilon(1e-5).unwrap().is_finite());
+
+        assert!(accountant.epsilon(0.0).is_err());
+        assert!(accountant
+            .record(&Mechanism::Skellam { mu: -1.0 }, 4, 1.0, 1.0)
+            .is_err());
+    }
+}
@@ -1,78 +1,76 @@
+use ark_bls12_381::{Fq, Fr, G1Affine, G1Projective};
+use ark_ec::{AffineRepr, CurveGroup, Group};
+use ark_ff::{PrimeField, Zero};
+use ark_std::UniformRand;
 use lazy_static::lazy_static;
-use num_bigint::BigInt;
-use num_traits::{One, Zero};
+use num_bigint::{BigInt, BigUint};
+use num_traits::One;
+use sha2::{Digest, Sha256};
 use std::ops::{Add, Mul, Neg};
 
+/// The group the value commitments and their proofs live in: G1 of
+/// BLS12-381, the group the MKHS signatures use too. `n` is its prime order.
 pub struct EC {
-    pub a: BigInt,
-    pub b: BigInt,
-    pub p: BigInt,
     pub n: BigInt,
 }
 
 lazy_static! {
     pub static ref DEFAULTEC: EC = EC {
-        a: BigInt::from(203298074u64),
-        b: BigInt::from(2030070442u64),
-        p: BigInt::from(2756527723u64),
-        n: BigInt::from(2756629331u64),
+        n: BigInt::from(BigUint::from(Fr::MODULUS)),
     };
+    static ref DEFAULTH: Point = Point::hash_to_curve(b"viper-generator-h");
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub struct Point {
-    pub x: Option<BigInt>,
-    pub y: Option<BigInt>,
-}
+pub struct Point(pub(crate) G1Projective);
 
 impl Default for Point {
     fn default() -> Self {
-        let x = BigInt::from(1668671046u64);
-        let y = BigInt::from(372808598u64);
-        Self {
-            x: Some(x),
-            y: Some(y),
-        }
+        Self(G1Projective::generator())
     }
 }
 
 impl Point {
-    pub const fn is_none(&self) -> bool {
-        self.x.is_none()
+    pub fn is_none(&self) -> bool {
+        self.0.is_zero()
     }
 
-    pub const fn infinity() -> Self {
-        Self { x: None, y: None }
+    pub fn infinity() -> Self {
+        Self(G1Projective::zero())
     }
 
-    pub fn double(&self) -> Self {
-        if self.is_none() {
-            return self.clone();
-        }
+    pub fn h() -> Self {
+        DEFAULTH.clone()
+    }
 
-        let p_x = self.x.clone().unwrap();
-        let p_y = self.y.clone().unwrap();
+    /// A point with no known discrete log relation to any other, by
+    /// try-and-increment on the x coordinate.
+    pub fn hash_to_curve(label: &[u8]) -> Self {
+        (0u64..)
+            .find_map(|counter| {
+                let digest = Sha256::new()
+                    .chain_update(label)
+                    .chain_update(counter.to_le_bytes())
+                    .finalize();
+                let x = Fq::from_le_bytes_mod_order(&digest);
+                let point = G1Affine::get_point_from_x_unchecked(x, false)?.clear_cofactor();
+                (!point.is_zero()).then(|| Self(point.into_group()))
+            })
+            .unwrap()
+    }
 
-        if p_y.is_zero() {
-            return Self::infinity();
-        }
+    pub fn double(&self) -> Self {
+        Self(self.0.double())
+    }
 
-        // (3 * pow(p[0], 2, self.p) + self.a) * gmpy2.invert(2 * p[1], self.p) % self.p
-        let l_1 = BigInt::from(3) * p_x.modpow(&BigInt::from(2), &DEFAULTEC.p) + &DEFAULTEC.a;
-        let l_2 = ((BigInt::from(2) * &p_y).modinv(&DEFAULTEC.p).unwrap())
-            .modpow(&BigInt::one(), &DEFAULTEC.p);
-        let l = l_1 * l_2;
-
-        // x3 = (pow(l, 2, self.p) - 2 * p[0]) % self.p
-        // y3 = (l * (p[0] - x3) - p[1]) % self.p
-        let x3 = (l.modpow(&BigInt::from(2), &DEFAULTEC.p) - (BigInt::from(2) * &p_x))
-            .modpow(&BigInt::one(), &DEFAULTEC.p);
-        let y3 = (l * (p_x - &x3) - p_y).modpow(&BigInt::one(), &DEFAULTEC.p);
-
-        Self {
-            x: Some(x3),
-            y: Some(y3),
-        }
+    pub fn to_affine(&self) -> G1Affine {
+        self.0.into_affine()
+    }
+}
+
+impl From<G1Projective> for Point {
+    fn from(point: G1Projective) -> Self {
+        Self(point)
     }
 }
 
@@ -80,14 +78,7 @@ impl Neg for Point {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
-        if self.is_none() {
-            return Self::infinity();
-        }
-
-        Self {
-            x: self.x.clone(),
-            y: self.y.map(|y| y.neg().modpow(&BigInt::one(), &DEFAULTEC.p)),
-        }
+        Self(-self.0)
     }
 }
 
@@ -95,40 +86,7 @@ impl Add for &Point {
     type Output = Point;
 
     fn add(self, rhs: Self) -> Self::Output {
-        if self.eq(rhs) {
-            return self.double();
-        }
-
-        if self.is_none() {
-            return rhs.clone();
-        }
-        if rhs.is_none() {
-            return self.clone();
-        }
-        if *rhs == -self.clone() {
-            return Point::infinity();
-        }
-
-        let p1_x = self.x.clone().unwrap();
-        let p1_y = self.y.clone().unwrap();
-        let p2_x = rhs.x.clone().unwrap();
-        let p2_y = rhs.y.clone().unwrap();
-
-        // l = (p2[1] - p1[1]) * gmpy2.invert(p2[0] - p1[0], self.p) % self.p
-        let l = ((p2_y - &p1_y) * (&p2_x - &p1_x).modinv(&DEFAULTEC.p).unwrap())
-            .modpow(&BigInt::one(), &DEFAULTEC.p);
-
-        // x3 = (pow(l, 2, self.p) - p1[0] - p2[0]) % self.p
-        let x3 = (l.modpow(&BigInt::from(2), &DEFAULTEC.p) - &p1_x - p2_x)
-            .modpow(&BigInt::one(), &DEFAULTEC.p);
-
-        // y3 = (l * (p1[0] - x3) - p1[1]) % self.p
-        let y3 = (l * (p1_x - &x3) - p1_y).modpow(&BigInt::one(), &DEFAULTEC.p);
-
-        Point {
-            x: Some(x3),
-            y: Some(y3),
-        }
+        Point(self.0 + rhs.0)
     }
 }
 
@@ -136,24 +94,54 @@ impl Mul<&BigInt> for Point {
     type Output = Self;
 
     fn mul(self, rhs: &BigInt) -> Self::Output {
-        if self.is_none() {
-            return self;
-        }
+        Self(self.0 * to_scalar(rhs))
+    }
+}
 
-        let mut res = Self::infinity();
-        let mut temp = self;
+/// `value mod n` as a scalar of the group.
+pub fn to_scalar(value: &BigInt) -> Fr {
+    let reduced = value.modpow(&BigInt::one(), &DEFAULTEC.n);
+    Fr::from(reduced.to_biguint().unwrap())
+}
 
-        let mut scalar = rhs.clone();
+pub fn from_scalar(value: &Fr) -> BigInt {
+    BigInt::from(BigUint::from(*value))
+}
 
-        while scalar > BigInt::zero() {
-            let bit = &scalar % 2;
-            scalar >>= 1;
-            if bit == BigInt::one() {
-                res = &res + &temp;
-            }
-            temp = temp.double();
+/// A uniform non-zero scalar.
+pub fn random_scalar() -> BigInt {
+    loop {
+        let scalar = Fr::rand(&mut rand::thread_rng());
+        if !scalar.is_zero() {
+            return from_scalar(&scalar);
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generator_h() {
+        let h = Point::h();
+        assert_ne!(h, Point::default());
+        assert!((h.clone() * &DEFAULTEC.n).is_none());
+        assert!(!(h * &(&DEFAULTEC.n - 1)).is_none());
+    }
 
-        res
+    #[test]
+    fn test_scalars() {
+        let g = Point::default();
+        assert_eq!(
+            g.clone() * &(&DEFAULTEC.n + 5),
+            g.clone() * &BigInt::from(5)
+        );
+        assert_eq!(g.clone() * &BigInt::from(-1), -g.clone());
+        assert_eq!(&g + &g, g.double());
+
+        let scalar = random_scalar();
+        assert_eq!(from_scalar(&to_scalar(&scalar)), scalar);
+        assert_eq!(from_scalar(&to_scalar(&BigInt::from(-1))), &DEFAULTEC.n - 1);
     }
 }
@@ -3,22 +3,25 @@ use ark_bls12_381::{Bls12_381, Config, Fr, G1Projective, G2Projective};
 use ark_ec::bls12::Bls12;
 use ark_ec::pairing::{Pairing, PairingOutput};
 use ark_ec::Group;
-use ark_ff::Field;
+use ark_ff::{Field, PrimeField};
 use ark_serialize::CanonicalSerializeHashExt;
-use ark_std::UniformRand;
+use ark_std::{UniformRand, Zero};
 use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::{thread_rng, SeedableRng};
 use rayon::prelude::*;
-use sha2::Sha256;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Lam {
-    client_id: u64,
-    sig: ed25519_dalek::Signature,
-    big_z: G2Projective,
-    big_a: G1Projective,
-    big_c: G1Projective,
+    pub(crate) client_id: u64,
+    pub(crate) sig: ed25519_dalek::Signature,
+    pub(crate) big_z: G2Projective,
+    pub(crate) big_a: G1Projective,
+    pub(crate) big_c: G1Projective,
+    /// Number of signatures of this client merged into the lam.
+    pub(crate) count: u64,
 }
 
 impl Lam {
@@ -26,6 +29,7 @@ impl Lam {
         Self {
             big_a: G1Projective::default(),
             big_c: G1Projective::default(),
+            count: 0,
             ..self.clone()
         }
     }
@@ -33,24 +37,41 @@ impl Lam {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Signature {
-    lams: Vec<Lam>,
-    big_r: G1Projective,
-    big_s: G2Projective,
+    pub(crate) lams: Vec<Lam>,
+    pub(crate) big_r: G1Projective,
+    pub(crate) big_s: G2Projective,
+}
+
+impl Signature {
+    /// Ids of the clients whose signatures were combined into this one.
+    pub fn participants(&self) -> BTreeSet<u64> {
+        self.lams.iter().map(|lam| lam.client_id).collect()
+    }
+}
+
+/// Proof of knowledge of the values of the columns outside a subset, as
+/// `rest = sum_i m_i * H_i` over those columns, so a signature can be
+/// verified against the subset's values alone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ColumnProof {
+    pub(crate) rest: G1Projective,
+    pub(crate) t: G1Projective,
+    pub(crate) z: Vec<Fr>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SK {
-    k: u64,
-    sk_sig: SigningKey,
-    xs: Vec<Fr>,
-    y: Fr,
+    pub(crate) k: u64,
+    pub(crate) sk_sig: SigningKey,
+    pub(crate) x: Fr,
+    pub(crate) y: Fr,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PK {
-    pk_sig: VerifyingKey,
-    hs: Vec<PairingOutput<Bls12<Config>>>,
-    big_y: G2Projective,
+    pub(crate) pk_sig: VerifyingKey,
+    pub(crate) h: PairingOutput<Bls12<Config>>,
+    pub(crate) big_y: G2Projective,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -61,16 +82,17 @@ pub struct KeyPair {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Mkhs {
-    g1: G1Projective,
-    g2: G2Projective,
-    gt: PairingOutput<Bls12<Config>>,
-    n: usize,
-    t: usize,
-    big_hs: Vec<G1Projective>,
+    pub(crate) g1: G1Projective,
+    pub(crate) g2: G2Projective,
+    pub(crate) gt: PairingOutput<Bls12<Config>>,
+    pub(crate) t: usize,
+    pub(crate) big_hs: Vec<G1Projective>,
 }
 
 impl Mkhs {
-    pub fn setup(n: usize, t: usize) -> Self {
+    /// Public parameters for messages of `t` values. They do not depend on
+    /// the number of clients, so clients can join at any time.
+    pub fn setup(t: usize) -> Self {
         let g1 = G1Projective::generator();
         let g2 = G2Projective::generator();
         let gt = Bls12_381::pairing(g1, g2);
@@ -84,36 +106,181 @@ impl Mkhs {
             g1,
             g2,
             gt,
-            n,
             t,
             big_hs,
         }
     }
 
     pub fn generate_keys(&self, client_id: u64) -> KeyPair {
-        let sk_sig = SigningKey::generate(&mut thread_rng());
-        let pk_sig = sk_sig.verifying_key();
+        let sk = SK {
+            k: client_id,
+            sk_sig: SigningKey::generate(&mut thread_rng()),
+            x: Fr::rand(&mut thread_rng()),
+            y: Fr::rand(&mut thread_rng()),
+        };
+        KeyPair {
+            pk: self.public_key(&sk),
+            sk,
+        }
+    }
 
-        let xs = vec![Fr::rand(&mut thread_rng()); self.n];
+    /// The `PK` matching `sk` under these parameters.
+    pub fn public_key(&self, sk: &SK) -> PK {
+        PK {
+            pk_sig: sk.sk_sig.verifying_key(),
+            h: self.gt * sk.x,
+            big_y: self.g2 * sk.y,
+        }
+    }
 
-        let y = Fr::rand(&mut thread_rng());
-        let big_y = self.g2 * y;
+    pub fn sign(&self, sk: &SK, message: &[Fr]) -> anyhow::Result<Signature> {
+        self.sign_labeled(sk, 0, message)
+    }
 
-        let hs = xs.par_iter().map(|v| self.gt * v).collect();
+    /// Signs `message` under `label` (e.g. a round id). The label is folded
+    /// into the client tag `Z`, so a signature only verifies for the label it
+    /// was produced under.
+    pub fn sign_labeled(&self, sk: &SK, label: u64, message: &[Fr]) -> anyhow::Result<Signature> {
+        Ok(self.sign_with_randomness(sk, label, message)?.0)
+    }
 
-        KeyPair {
-            sk: SK {
-                k: client_id,
-                sk_sig,
-                xs,
-                y,
-            },
-            pk: PK { pk_sig, hs, big_y },
+    /// Like [`Mkhs::sign_labeled`], also returning the randomness `s` of
+    /// `C = s * g1 + sum_i m_i * H_i`, which proofs about the signed message
+    /// need as a witness.
+    ///
+    /// Messages shorter than `t` are padded with zeros, longer ones are
+    /// rejected.
+    pub fn sign_with_randomness(
+        &self,
+        sk: &SK,
+        label: u64,
+        message: &[Fr],
+    ) -> anyhow::Result<(Signature, Fr)> {
+        if message.len() > self.t {
+            return Err(anyhow!(
+                "Message of {} values, at most {} supported.",
+                message.len(),
+                self.t
+            ));
+        }
+        Ok(self.sign_with_generators(sk, label, message, &self.big_hs))
+    }
+
+    /// Signs a whole dataset in one signature, as a single message with one
+    /// generator per cell, instead of one signature per row. Evaluating the
+    /// dataset signatures of several clients gives one signature on the
+    /// aggregated dataset, checked with [`Mkhs::verify_dataset`].
+    ///
+    /// `context`, e.g. a digest of the dataset's schema, is hashed into
+    /// every generator, so the signature only verifies under that context.
+    pub fn sign_dataset(
+        &self,
+        sk: &SK,
+        label: u64,
+        context: &[u8],
+        rows: &[Vec<Fr>],
+    ) -> anyhow::Result<Signature> {
+        Ok(self
+            .sign_dataset_with_randomness(sk, label, context, rows)?
+            .0)
+    }
+
+    /// Like [`Mkhs::sign_dataset`], also returning the randomness `s` of
+    /// `C = s * g1 + sum_k m_k * H_k`, which the consistency proof of an
+    /// upload needs as a witness.
+    pub fn sign_dataset_with_randomness(
+        &self,
+        sk: &SK,
+        label: u64,
+        context: &[u8],
+        rows: &[Vec<Fr>],
+    ) -> anyhow::Result<(Signature, Fr)> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != cols) {
+            return Err(anyhow!("Dataset rows must all have the same length."));
+        }
+        let generators = self.dataset_generators(context, rows.len(), cols)?;
+        let message: Vec<Fr> = rows.concat();
+        Ok(self.sign_with_generators(sk, label, &message, &generators))
+    }
+
+    /// Verifies a dataset signature, e.g. one aggregated with [`Mkhs::eval`]
+    /// over the dataset signatures of several clients, under the `context`
+    /// it was signed with.
+    pub fn verify_dataset(
+        &self,
+        pks: &HashMap<u64, PK>,
+        context: &[u8],
+        rows: &[Vec<Fr>],
+        signature: &Signature,
+    ) -> anyhow::Result<()> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != cols) {
+            return Err(anyhow!("Dataset rows must all have the same length."));
+        }
+        let generators = self.dataset_generators(context, rows.len(), cols)?;
+        let msg_part = rows
+            .concat()
+            .par_iter()
+            .zip(generators.par_iter())
+            .map(|(m, h)| *h * m)
+            .sum();
+        self.verify_message_part(pks, msg_part, signature)
+    }
+
+    /// Like [`Mkhs::verify_participants`], for a dataset signature.
+    pub fn verify_dataset_participants(
+        &self,
+        pks: &HashMap<u64, PK>,
+        expected: &BTreeSet<u64>,
+        context: &[u8],
+        rows: &[Vec<Fr>],
+        signature: &Signature,
+    ) -> anyhow::Result<()> {
+        check_participants(expected, signature)?;
+        self.verify_dataset(pks, context, rows, signature)
+    }
+
+    /// One generator per cell of a `rows` by `cols` dataset, in row-major
+    /// order. Cell `(i, j)` gets a point hashed from `context`, `H_j` and
+    /// `i`, so no discrete log relation between them is known and the public
+    /// parameters stay independent of the number of rows.
+    pub(crate) fn dataset_generators(
+        &self,
+        context: &[u8],
+        rows: usize,
+        cols: usize,
+    ) -> anyhow::Result<Vec<G1Projective>> {
+        if cols > self.t {
+            return Err(anyhow!(
+                "Dataset rows of {} values, at most {} supported.",
+                cols,
+                self.t
+            ));
         }
+        Ok((0..rows * cols)
+            .into_par_iter()
+            .map(|k| {
+                let seed = Sha256::new()
+                    .chain_update(b"viper-dataset-generator")
+                    .chain_update((context.len() as u64).to_le_bytes())
+                    .chain_update(context)
+                    .chain_update(self.big_hs[k % cols].hash::<Sha256>())
+                    .chain_update(((k / cols) as u64).to_le_bytes())
+                    .finalize();
+                G1Projective::rand(&mut StdRng::from_seed(seed.into()))
+            })
+            .collect())
     }
 
-    pub fn sign(&self, sk: &SK, message: &[Fr]) -> Signature {
-        let z = Fr::from(sk.k);
+    fn sign_with_generators(
+        &self,
+        sk: &SK,
+        label: u64,
+        message: &[Fr],
+        generators: &[G1Projective],
+    ) -> (Signature, Fr) {
+        let z = tag(sk.k, label);
         let big_z = self.g2 * z;
 
         let temp = big_z.hash::<Sha256>();
@@ -122,94 +289,240 @@ impl Mkhs {
         let r = Fr::rand(&mut thread_rng());
         let s = Fr::rand(&mut thread_rng());
 
-        let mut big_a = self.g1 * (sk.xs[0] + r);
+        let mut big_a = self.g1 * (sk.x + r);
         let mut big_c = self.g1 * s;
 
-        for (i, v) in self.big_hs.iter().enumerate() {
-            big_a += *v * (sk.y + message[i]);
-            big_c += *v * message[i]
+        for (i, v) in generators.iter().enumerate() {
+            let m = message.get(i).copied().unwrap_or_default();
+            big_c += *v * m;
         }
 
+        big_a += big_c * sk.y;
         big_a *= z.inverse().unwrap();
 
-        let big_r = self.g1 * (r - (sk.y + s));
+        let big_r = self.g1 * r;
         let big_s = self.g2 * (-s);
 
-        Signature {
+        let signature = Signature {
             lams: vec![Lam {
                 client_id: sk.k,
                 sig,
                 big_z,
                 big_a,
                 big_c,
+                count: 1,
             }],
             big_r,
             big_s,
-        }
+        };
+        (signature, s)
     }
 
+    /// Combines signatures, including partial aggregates. Lams of the same
+    /// client under the same tag are merged, so a client may contribute
+    /// several signatures.
     pub fn eval(&self, sigs: &[Signature]) -> Signature {
         let mut big_r = G1Projective::default();
         let mut big_s = G2Projective::default();
 
-        let mut id_lam = HashMap::new();
-        for sig in sigs.iter().take(self.n) {
+        let mut lams: Vec<Lam> = vec![];
+        let mut positions: HashMap<(u64, G2Projective), usize> = HashMap::new();
+        for sig in sigs.iter() {
             big_r += sig.big_r;
             big_s += sig.big_s;
 
             for lam in sig.lams.iter() {
-                let mut temp = lam.clone_empty();
-                temp.big_a += lam.big_a;
-                temp.big_c += lam.big_c;
-
-                id_lam.insert(temp.client_id, temp);
+                let i = *positions
+                    .entry((lam.client_id, lam.big_z))
+                    .or_insert_with(|| {
+                        lams.push(lam.clone_empty());
+                        lams.len() - 1
+                    });
+                let merged = &mut lams[i];
+                merged.big_a += lam.big_a;
+                merged.big_c += lam.big_c;
+                merged.count += lam.count;
             }
         }
 
-        Signature {
-            lams: id_lam.values().cloned().collect(),
-            big_r,
-            big_s,
-        }
+        Signature { lams, big_r, big_s }
+    }
+
+    /// Checks that every client tag in `signature` was produced under `label`.
+    pub fn verify_label(&self, signature: &Signature, label: u64) -> anyhow::Result<()> {
+        signature.lams.iter().try_for_each(|v| {
+            if v.big_z != self.g2 * tag(v.client_id, label) {
+                return Err(anyhow!(
+                    "Signature of client {} is not bound to label {}.",
+                    v.client_id,
+                    label
+                ));
+            }
+            Ok(())
+        })
     }
 
+    /// Verifies `signature` and checks it combines exactly the signatures of
+    /// the `expected` clients.
+    pub fn verify_participants(
+        &self,
+        pks: &HashMap<u64, PK>,
+        expected: &BTreeSet<u64>,
+        messages: &[Fr],
+        signature: &Signature,
+    ) -> anyhow::Result<()> {
+        check_participants(expected, signature)?;
+        self.verify(pks, messages, signature)
+    }
+
+    /// Verifies `signature` against the keys of the clients it names, which
+    /// must all be in `pks`. Other keys in `pks` are ignored. Messages
+    /// shorter than `t` are padded with zeros.
     pub fn verify(
         &self,
         pks: &HashMap<u64, PK>,
         messages: &[Fr],
         signature: &Signature,
     ) -> anyhow::Result<()> {
-        signature.lams.par_iter().try_for_each(|v| {
-            let key = pks
-                .get(&v.client_id)
-                .ok_or_else(|| anyhow!("Key not found."))?;
-            key.pk_sig
-                .verify(&v.big_z.hash::<Sha256>(), &v.sig)
-                .map_err(|_| anyhow!("Failed to verify signature."))
-        })?;
-
-        let mut a_z_pairs = PairingOutput::default();
-        let mut c_y_pairs = PairingOutput::default();
+        if messages.len() > self.t {
+            return Err(anyhow!(
+                "Message of {} values, at most {} supported.",
+                messages.len(),
+                self.t
+            ));
+        }
+        let msg_part = messages
+            .iter()
+            .zip(self.big_hs.iter())
+            .fold(G1Projective::default(), |acc, (m, h)| acc + *h * m);
+        self.verify_message_part(pks, msg_part, signature)
+    }
+
+    /// Proves the values of `message` outside `columns`, so that `signature`
+    /// on `message` can be checked with [`Mkhs::verify_columns`] given only
+    /// the values in `columns`.
+    pub fn prove_columns(&self, message: &[Fr], columns: &[usize]) -> anyhow::Result<ColumnProof> {
+        let rest = self.rest_columns(columns)?;
+        if message.len() > self.t {
+            return Err(anyhow!(
+                "Message of {} values, at most {} supported.",
+                message.len(),
+                self.t
+            ));
+        }
+        let ks: Vec<Fr> = rest.iter().map(|_| Fr::rand(&mut thread_rng())).collect();
+        let commit = |values: &[Fr]| {
+            rest.iter()
+                .zip(values)
+                .fold(G1Projective::default(), |acc, (i, v)| {
+                    acc + self.big_hs[*i] * v
+                })
+        };
+        let ms: Vec<Fr> = rest
+            .iter()
+            .map(|i| message.get(*i).copied().unwrap_or_default())
+            .collect();
+        let (big_rest, t) = (commit(&ms), commit(&ks));
+        let e = column_challenge(columns, &big_rest, &t);
+        Ok(ColumnProof {
+            rest: big_rest,
+            t,
+            z: ks.iter().zip(ms.iter()).map(|(k, m)| *k + e * m).collect(),
+        })
+    }
+
+    /// Verifies `signature` on a message whose values in `columns` are
+    /// `values`, the other columns being covered by `proof`. With a
+    /// signature evaluated over several rows, this checks e.g. the sum of a
+    /// single column.
+    pub fn verify_columns(
+        &self,
+        pks: &HashMap<u64, PK>,
+        columns: &[usize],
+        values: &[Fr],
+        proof: &ColumnProof,
+        signature: &Signature,
+    ) -> anyhow::Result<()> {
+        let rest = self.rest_columns(columns)?;
+        if values.len() != columns.len() || proof.z.len() != rest.len() {
+            return Err(anyhow!("Column proof does not match the columns."));
+        }
+        let e = column_challenge(columns, &proof.rest, &proof.t);
+        let lhs = rest
+            .iter()
+            .zip(proof.z.iter())
+            .fold(G1Projective::default(), |acc, (i, z)| {
+                acc + self.big_hs[*i] * z
+            });
+        if lhs != proof.t + proof.rest * e {
+            return Err(anyhow!("Invalid column proof."));
+        }
+        let msg_part = columns
+            .iter()
+            .zip(values)
+            .fold(proof.rest, |acc, (i, v)| acc + self.big_hs[*i] * v);
+        self.verify_message_part(pks, msg_part, signature)
+    }
+
+    /// The columns not in `columns`, which must be distinct and below `t`.
+    fn rest_columns(&self, columns: &[usize]) -> anyhow::Result<Vec<usize>> {
+        let subset: BTreeSet<usize> = columns.iter().copied().collect();
+        if subset.len() != columns.len() || subset.iter().any(|i| *i >= self.t) {
+            return Err(anyhow!("Columns must be distinct and below {}.", self.t));
+        }
+        Ok((0..self.t).filter(|i| !subset.contains(i)).collect())
+    }
+
+    /// Verifies `signature` given `msg_part = sum_i m_i * H_i`.
+    fn verify_message_part(
+        &self,
+        pks: &HashMap<u64, PK>,
+        msg_part: G1Projective,
+        signature: &Signature,
+    ) -> anyhow::Result<()> {
+        // A lam that merges no signatures adds nothing to either equation, so
+        // it would let a client appear as a participant without signing.
+        if let Some(v) = signature.lams.iter().find(|v| v.count == 0) {
+            return Err(anyhow!(
+                "Signature of client {} merges no signatures.",
+                v.client_id
+            ));
+        }
+        let keys = signature
+            .lams
+            .iter()
+            .map(|v| {
+                pks.get(&v.client_id)
+                    .ok_or_else(|| anyhow!("Key not found for client {}.", v.client_id))
+            })
+            .collect::<anyhow::Result<Vec<&PK>>>()?;
+        signature
+            .lams
+            .par_iter()
+            .zip(keys.par_iter())
+            .try_for_each(|(v, key)| {
+                key.pk_sig
+                    .verify(&v.big_z.hash::<Sha256>(), &v.sig)
+                    .map_err(|_| anyhow!("Failed to verify signature."))
+            })?;
+
+        let mut a_z_pairs = PairingOutput::zero();
+        let mut c_y_pairs = PairingOutput::zero();
         let mut c_tot = G1Projective::default();
 
-        for v in signature.lams.iter() {
+        for (v, key) in signature.lams.iter().zip(keys.iter()) {
             a_z_pairs += Bls12_381::pairing(v.big_a, v.big_z);
-            c_y_pairs += Bls12_381::pairing(v.big_c, pks.get(&v.client_id).unwrap().big_y);
+            c_y_pairs += Bls12_381::pairing(v.big_c, key.big_y);
             c_tot += v.big_c
         }
 
         let big_r_pair = Bls12_381::pairing(signature.big_r, self.g2);
         let big_s_pair = Bls12_381::pairing(self.g1, signature.big_s);
 
-        let mut tags_scale_part = PairingOutput::default();
-
-        for pk in pks.values() {
-            tags_scale_part += pk.hs[0];
-        }
+        let mut tags_scale_part = PairingOutput::zero();
 
-        let mut msg_part = G1Projective::default();
-        for (i, message) in messages.iter().enumerate() {
-            msg_part += self.big_hs[i] * message
+        for (v, key) in signature.lams.iter().zip(keys.iter()) {
+            tags_scale_part += key.h * Fr::from(v.count);
         }
 
         let p2 = tags_scale_part + c_y_pairs + big_r_pair;
@@ -219,7 +532,7 @@ impl Mkhs {
         let e1 = a_z_pairs == p2;
         let e2 = p3 == p4;
 
-        if e1 != e2 {
+        if !(e1 && e2) {
             println!("Verification Failed.");
             return Err(anyhow!("Verification Failed."));
         }
@@ -228,6 +541,44 @@ impl Mkhs {
     }
 }
 
+/// Checks that `signature` combines exactly the signatures of the
+/// `expected` clients.
+fn check_participants(expected: &BTreeSet<u64>, signature: &Signature) -> anyhow::Result<()> {
+    let participants = signature.participants();
+    let missing: Vec<u64> = expected.difference(&participants).copied().collect();
+    let extra: Vec<u64> = participants.difference(expected).copied().collect();
+    match (missing.is_empty(), extra.is_empty()) {
+        (true, true) => Ok(()),
+        (false, true) => Err(anyhow!("Missing participants {:?}.", missing)),
+        (true, false) => Err(anyhow!("Unexpected participants {:?}.", extra)),
+        (false, false) => Err(anyhow!(
+            "Missing participants {:?}, unexpected participants {:?}.",
+            missing,
+            extra
+        )),
+    }
+}
+
+fn column_challenge(columns: &[usize], rest: &G1Projective, t: &G1Projective) -> Fr {
+    let mut hasher = Sha256::new().chain_update(b"viper-columns");
+    for i in columns {
+        hasher.update((*i as u64).to_le_bytes());
+    }
+    let digest = hasher
+        .chain_update(rest.hash::<Sha256>())
+        .chain_update(t.hash::<Sha256>())
+        .finalize();
+    Fr::from_le_bytes_mod_order(&digest)
+}
+
+fn tag(client_id: u64, label: u64) -> Fr {
+    let digest = Sha256::new()
+        .chain_update(client_id.to_le_bytes())
+        .chain_update(label.to_le_bytes())
+        .finalize();
+    Fr::from_le_bytes_mod_order(&digest)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -235,41 +586,79 @@ mod tests {
 
     #[test]
     fn test_single_signature() {
-        let n = 2usize;
         let t = 2usize;
 
-        let mkhs = Mkhs::setup(n, t);
+        let mkhs = Mkhs::setup(t);
         let id = random();
         let key = mkhs.generate_keys(id);
 
         let messages = vec![Fr::from(2), Fr::from(10)];
 
-        let signature = mkhs.sign(&key.sk, &messages);
+        let signature = mkhs.sign(&key.sk, &messages).unwrap();
         let check = mkhs.verify(&HashMap::from([(id, key.pk)]), &messages, &signature);
 
         assert!(check.is_ok());
+
+        // Messages longer than `t` are rejected rather than truncated.
+        let long = vec![Fr::from(1); t + 1];
+        assert!(mkhs.sign(&key.sk, &long).is_err());
+    }
+
+    #[test]
+    fn test_tampered_signature() {
+        let mkhs = Mkhs::setup(2);
+        let id = random();
+        let key = mkhs.generate_keys(id);
+
+        let messages = vec![Fr::from(2), Fr::from(10)];
+        let mut signature = mkhs.sign(&key.sk, &messages).unwrap();
+
+        // Breaks both pairing equations at once.
+        signature.big_r += mkhs.g1;
+        let messages = vec![Fr::from(3), Fr::from(10)];
+        let check = mkhs.verify(&HashMap::from([(id, key.pk)]), &messages, &signature);
+        assert!(check.is_err());
+    }
+
+    #[test]
+    fn test_empty_lam() {
+        let mkhs = Mkhs::setup(2);
+        let (id, id2) = (random(), random());
+        let key = mkhs.generate_keys(id);
+        let key2 = mkhs.generate_keys(id2);
+        let pks = HashMap::from([(id, key.pk), (id2, key2.pk)]);
+        let messages = vec![Fr::from(2), Fr::from(10)];
+
+        // Client 2's tag with nothing signed under it.
+        let mut signature = mkhs.sign(&key.sk, &messages).unwrap();
+        let empty = mkhs.sign(&key2.sk, &messages).unwrap().lams[0].clone_empty();
+        signature.lams.push(empty);
+        let expected = BTreeSet::from([id, id2]);
+        assert!(mkhs
+            .verify_participants(&pks, &expected, &messages, &signature)
+            .is_err());
+        assert!(mkhs.verify(&pks, &messages, &signature).is_err());
     }
 
     #[test]
     fn test_aggregated_signature() {
-        let n = 2usize;
         let t = 2usize;
 
-        let mkhs = Mkhs::setup(n, t);
+        let mkhs = Mkhs::setup(t);
 
         // First user
         let id = random();
         let key = mkhs.generate_keys(id);
 
         let messages = vec![Fr::from(2), Fr::from(10)];
-        let signature = mkhs.sign(&key.sk, &messages);
+        let signature = mkhs.sign(&key.sk, &messages).unwrap();
 
         // Second user
         let id2 = random();
         let key2 = mkhs.generate_keys(id2);
 
         let messages2 = vec![Fr::from(2), Fr::from(10)];
-        let signature2 = mkhs.sign(&key2.sk, &messages2);
+        let signature2 = mkhs.sign(&key2.sk, &messages2).unwrap();
 
         // Combined signatures
         let combined_messages: Vec<Fr> = messages
@@ -287,4 +676,141 @@ mod tests {
         println!("check: {:?}", check);
         assert!(check.is_ok());
     }
+
+    #[test]
+    fn test_verify_participants() {
+        let mkhs = Mkhs::setup(2);
+        let (id, id2, id3) = (random(), random(), random());
+        let key = mkhs.generate_keys(id);
+        let key2 = mkhs.generate_keys(id2);
+        let messages = vec![Fr::from(2), Fr::from(10)];
+        let combined_messages: Vec<Fr> = messages.iter().map(|v| *v + v).collect();
+        let combined_signatures = mkhs.eval(&[
+            mkhs.sign(&key.sk, &messages).unwrap(),
+            mkhs.sign(&key2.sk, &messages).unwrap(),
+        ]);
+
+        // Extra keys are ignored, unless the caller expects those clients.
+        let pks = HashMap::from([
+            (id, key.pk),
+            (id2, key2.pk),
+            (id3, mkhs.generate_keys(id3).pk),
+        ]);
+        assert!(mkhs
+            .verify(&pks, &combined_messages, &combined_signatures)
+            .is_ok());
+        let expected = BTreeSet::from([id, id2]);
+        assert!(mkhs
+            .verify_participants(&pks, &expected, &combined_messages, &combined_signatures)
+            .is_ok());
+        let check = mkhs.verify_participants(
+            &pks,
+            &BTreeSet::from([id, id3]),
+            &combined_messages,
+            &combined_signatures,
+        );
+        assert_eq!(
+            check.unwrap_err().to_string(),
+            format!(
+                "Missing participants [{}], unexpected participants [{}].",
+                id3, id2
+            )
+        );
+    }
+
+    #[test]
+    fn test_same_client_signatures() {
+        let mkhs = Mkhs::setup(2);
+        let (id, id2) = (1, 2);
+        let key = mkhs.generate_keys(id);
+        let key2 = mkhs.generate_keys(id2);
+        let pks = HashMap::from([(id, key.pk.clone()), (id2, key2.pk.clone())]);
+
+        let rows = [
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::from(4)],
+            vec![Fr::from(5), Fr::from(6)],
+        ];
+        let signatures = [
+            mkhs.sign(&key.sk, &rows[0]).unwrap(),
+            mkhs.sign(&key.sk, &rows[1]).unwrap(),
+            mkhs.sign(&key2.sk, &rows[2]).unwrap(),
+        ];
+        let total = vec![Fr::from(9), Fr::from(12)];
+
+        let combined = mkhs.eval(&signatures);
+        assert_eq!(combined.lams.len(), 2);
+        assert!(mkhs.verify(&pks, &total, &combined).is_ok());
+
+        // Partial aggregates combine into the same result.
+        let partial = mkhs.eval(&signatures[..2]);
+        assert!(mkhs
+            .verify(&pks, &[Fr::from(4), Fr::from(6)], &partial)
+            .is_ok());
+        let hierarchical = mkhs.eval(&[partial, signatures[2].clone()]);
+        assert!(mkhs.verify(&pks, &total, &hierarchical).is_ok());
+        assert!(mkhs.verify(&pks, &rows[2], &hierarchical).is_err());
+    }
+
+    #[test]
+    fn test_column_subset() {
+        let mkhs = Mkhs::setup(3);
+        let key = mkhs.generate_keys(1);
+        let pks = HashMap::from([(1, key.pk.clone())]);
+
+        // The last row is shorter and padded with zeros.
+        let rows = [
+            vec![Fr::from(1), Fr::from(2), Fr::from(3)],
+            vec![Fr::from(4), Fr::from(5), Fr::from(6)],
+            vec![Fr::from(7), Fr::from(8)],
+        ];
+        let signatures: Vec<Signature> = rows
+            .iter()
+            .map(|row| mkhs.sign(&key.sk, row).unwrap())
+            .collect();
+        assert!(mkhs.verify(&pks, &rows[2], &signatures[2]).is_ok());
+        let padded = [rows[2].clone(), vec![Fr::from(0)]].concat();
+        assert!(mkhs.verify(&pks, &padded, &signatures[2]).is_ok());
+        assert!(mkhs
+            .verify(&pks, &[padded, vec![Fr::from(0)]].concat(), &signatures[2])
+            .is_err());
+
+        // Sum of the last column across all rows, without the other columns.
+        let sum = mkhs.eval(&signatures);
+        let totals = vec![Fr::from(12), Fr::from(15), Fr::from(9)];
+        let proof = mkhs.prove_columns(&totals, &[2]).unwrap();
+        assert!(mkhs
+            .verify_columns(&pks, &[2], &[Fr::from(9)], &proof, &sum)
+            .is_ok());
+        assert!(mkhs
+            .verify_columns(&pks, &[2], &[Fr::from(10)], &proof, &sum)
+            .is_err());
+        assert!(mkhs
+            .verify_columns(&pks, &[1], &[Fr::from(15)], &proof, &sum)
+            .is_err());
+
+        // Shifting value into the hidden columns breaks the proof.
+        let mut forged = proof.clone();
+        forged.rest -= mkhs.big_hs[2];
+        assert!(mkhs
+            .verify_columns(&pks, &[2], &[Fr::from(10)], &forged, &sum)
+            .is_err());
+        assert!(mkhs.prove_columns(&totals, &[2, 2]).is_err());
+        assert!(mkhs.prove_columns(&totals, &[3]).is_err());
+    }
+
+    #[test]
+    fn test_labeled_signature() {
+        let mkhs = Mkhs::setup(2);
+        let id = random();
+        let key = mkhs.generate_keys(id);
+
+        let messages = vec![Fr::from(3), Fr::from(4)];
+        let signature = mkhs.sign_labeled(&key.sk, 7, &messages).unwrap();
+
+        let pks = HashMap::from([(id, key.pk)]);
+        assert!(mkhs.verify(&pks, &messages, &signature).is_ok());
+        assert!(mkhs.verify_label(&signature, 7).is_ok());
+        assert!(mkhs.verify_label(&signature, 8).is_err());
+    }
 }
@@ -1,4 +1,11 @@
 pub mod commitment;
+pub mod consistency_proof;
+pub mod dlog;
+pub mod dp;
 pub mod ec;
 pub mod mkhs;
-pub mod phollard_rho;
+pub mod norm_proof;
+pub mod opening_proof;
+pub mod range_proof;
+pub mod shamir;
+pub mod stats_proof;
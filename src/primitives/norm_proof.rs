@@ -0,0 +1,322 @@
+use crate::primitives::commitment::Commitment;
+use crate::primitives::ec::{random_scalar, Point, DEFAULTEC};
+use crate::primitives::range_proof::{
+    challenge, mul, pedersen, reduce, PedersenRangeProof, RangeProof,
+};
+use anyhow::anyhow;
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
+use rayon::prelude::*;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Norm {
+    LInf,
+    L2,
+}
+
+/// Bound on the norm of a row, measured as its distance from `center` in
+/// every column (e.g. the fixed-point offset of a model update).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NormBound {
+    pub norm: Norm,
+    pub center: BigInt,
+    pub bound: BigInt,
+}
+
+/// Proof that `Q = d * D + tau * H` where `D = d * G + delta * H` commits to
+/// the distance `d = w - center` of the value committed in `(C, S)`. `Q` is
+/// then a Pedersen commitment to `d^2` with randomness `d * delta + tau`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SquareProof {
+    pub(crate) big_d: Point,
+    pub(crate) big_q: Point,
+    pub(crate) e: BigInt,
+    pub(crate) z_d: BigInt,
+    pub(crate) z_delta: BigInt,
+    pub(crate) z_tau: BigInt,
+    pub(crate) z_r: BigInt,
+}
+
+/// Proof that a committed row has bounded norm. Every cell is proven within
+/// `center ± bound`; for L2, the committed squares of the distances are also
+/// proven to add up to at most `bound^2`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NormProof {
+    pub(crate) cells: Vec<RangeProof>,
+    pub(crate) squares: Vec<SquareProof>,
+    pub(crate) sum: Option<PedersenRangeProof>,
+}
+
+impl NormBound {
+    pub const fn new(norm: Norm, center: BigInt, bound: BigInt) -> Self {
+        Self {
+            norm,
+            center,
+            bound,
+        }
+    }
+
+    /// Checks the bound can be proven for rows of `cols` values without the
+    /// sum of squares wrapping around the group order.
+    pub fn check(&self, cols: usize) -> anyhow::Result<()> {
+        if self.bound.is_negative() {
+            return Err(anyhow!("Norm bound must be non-negative."));
+        }
+        if self.norm == Norm::L2 && &self.bound * &self.bound * cols >= DEFAULTEC.n {
+            return Err(anyhow!(
+                "L2 bound {} is too large for {} columns.",
+                self.bound,
+                cols
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn min(&self) -> BigInt {
+        &self.center - &self.bound
+    }
+
+    pub fn max(&self) -> BigInt {
+        &self.center + &self.bound
+    }
+}
+
+impl NormProof {
+    /// Proves `row`, committed with `randomness` under `generators`, one
+    /// per cell.
+    pub fn prove(
+        bound: &NormBound,
+        row: &[BigInt],
+        randomness: &BigInt,
+        generators: &[Point],
+    ) -> anyhow::Result<Self> {
+        bound.check(row.len())?;
+        if row.len() != generators.len() {
+            return Err(anyhow!("Row and generators have different lengths."));
+        }
+        let (min, max) = (bound.min(), bound.max());
+        let cells = row
+            .par_iter()
+            .zip(generators)
+            .map(|(w, h)| RangeProof::prove(w, randomness, h, &min, &max))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if bound.norm == Norm::LInf {
+            return Ok(Self {
+                cells,
+                squares: vec![],
+                sum: None,
+            });
+        }
+
+        let (squares, sigmas): (Vec<SquareProof>, Vec<BigInt>) = row
+            .par_iter()
+            .zip(generators)
+            .map(|(w, h)| SquareProof::prove(w, randomness, h, &bound.center))
+            .unzip();
+        let total: BigInt = row.iter().map(|w| (w - &bound.center).pow(2)).sum();
+        let sigma = reduce(&sigmas.iter().sum());
+        let sum = PedersenRangeProof::prove(&total, &sigma, &BigInt::zero(), &bound.bound.pow(2))
+            .map_err(|_| anyhow!("Row L2 norm exceeds {}.", bound.bound))?;
+
+        Ok(Self {
+            cells,
+            squares,
+            sum: Some(sum),
+        })
+    }
+
+    pub fn verify(
+        &self,
+        bound: &NormBound,
+        commitments: &[Commitment],
+        randomness_commitment: &Commitment,
+        generators: &[Point],
+    ) -> anyhow::Result<()> {
+        bound.check(commitments.len())?;
+        if self.cells.len() != commitments.len() || generators.len() != commitments.len() {
+            return Err(anyhow!("Norm proof does not match the row length."));
+        }
+        let (min, max) = (bound.min(), bound.max());
+        self.cells
+            .par_iter()
+            .zip(commitments)
+            .zip(generators)
+            .try_for_each(|((proof, c), h)| {
+                proof.verify(c, randomness_commitment, h, &min, &max)
+            })?;
+
+        match (bound.norm, &self.sum) {
+            (Norm::LInf, None) if self.squares.is_empty() => Ok(()),
+            (Norm::L2, Some(sum)) if self.squares.len() == commitments.len() => {
+                self.squares
+                    .par_iter()
+                    .zip(commitments)
+                    .zip(generators)
+                    .try_for_each(|((proof, c), h)| {
+                        proof.verify(&bound.center, &c.c, &randomness_commitment.c, h)
+                    })?;
+                let total = self
+                    .squares
+                    .iter()
+                    .fold(Point::infinity(), |acc, el| &acc + &el.big_q);
+                sum.verify(&total, &BigInt::zero(), &bound.bound.pow(2))
+                    .map_err(|_| anyhow!("Row L2 norm exceeds {}.", bound.bound))
+            }
+            _ => Err(anyhow!(
+                "Norm proof does not match the {:?} bound.",
+                bound.norm
+            )),
+        }
+    }
+}
+
+impl SquareProof {
+    /// Returns the proof and the randomness of `Q` as a commitment to `d^2`.
+    pub(crate) fn prove(w: &BigInt, r: &BigInt, h: &Point, center: &BigInt) -> (Self, BigInt) {
+        let c = Commitment::commit(w, r, h).c;
+        let s = Commitment::commit_randomness(r).c;
+        let d = &(w - center);
+        let (delta, tau) = (random_scalar(), random_scalar());
+        let big_d = pedersen(d, &delta);
+        let big_q = &mul(&big_d, d) + &mul(&Point::h(), &tau);
+
+        let (k_d, k_delta, k_tau, k_r) = (
+            random_scalar(),
+            random_scalar(),
+            random_scalar(),
+            random_scalar(),
+        );
+        let ts = [
+            pedersen(&k_d, &k_delta),
+            &mul(&big_d, &k_d) + &mul(&Point::h(), &k_tau),
+            &mul(h, &k_r) + &mul(&Point::h(), &-&k_delta),
+            mul(&Point::h(), &k_r),
+        ];
+        let x = link_target(&c, center, &big_d);
+        let e = challenge(
+            b"square",
+            &[
+                &c, &s, h, &big_d, &big_q, &x, &ts[0], &ts[1], &ts[2], &ts[3],
+            ],
+        );
+
+        let proof = Self {
+            z_d: reduce(&(k_d + &e * d)),
+            z_delta: reduce(&(k_delta + &e * &delta)),
+            z_tau: reduce(&(k_tau + &e * &tau)),
+            z_r: reduce(&(k_r + &e * r)),
+            big_d,
+            big_q,
+            e,
+        };
+        (proof, reduce(&(d * delta + tau)))
+    }
+
+    pub(crate) fn verify(
+        &self,
+        center: &BigInt,
+        c: &Point,
+        s: &Point,
+        h: &Point,
+    ) -> anyhow::Result<()> {
+        let e = &-&self.e;
+        let x = link_target(c, center, &self.big_d);
+        let ts = [
+            &pedersen(&self.z_d, &self.z_delta) + &mul(&self.big_d, e),
+            &(&mul(&self.big_d, &self.z_d) + &mul(&Point::h(), &self.z_tau)) + &mul(&self.big_q, e),
+            &(&mul(h, &self.z_r) + &mul(&Point::h(), &-&self.z_delta)) + &mul(&x, e),
+            &mul(&Point::h(), &self.z_r) + &mul(s, e),
+        ];
+        let expected = challenge(
+            b"square",
+            &[
+                c,
+                s,
+                h,
+                &self.big_d,
+                &self.big_q,
+                &x,
+                &ts[0],
+                &ts[1],
+                &ts[2],
+                &ts[3],
+            ],
+        );
+        if expected != self.e {
+            return Err(anyhow!("Invalid square proof."));
+        }
+        Ok(())
+    }
+}
+
+/// `C - center * G - D = r * h - delta * H`.
+fn link_target(c: &Point, center: &BigInt, big_d: &Point) -> Point {
+    &(c + &mul(&Point::default(), &-center)) + &(-big_d.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::commitment::generators;
+
+    fn commit(row: &[BigInt], r: &BigInt, hs: &[Point]) -> (Vec<Commitment>, Commitment) {
+        (
+            row.iter()
+                .zip(hs)
+                .map(|(w, h)| Commitment::commit(w, r, h))
+                .collect(),
+            Commitment::commit_randomness(r),
+        )
+    }
+
+    #[test]
+    fn test_l2_norm_proof() {
+        // Distances from the center are (3, -4), with norm 5.
+        let bound = NormBound::new(Norm::L2, BigInt::from(100), BigInt::from(5));
+        let row = vec![BigInt::from(103), BigInt::from(96)];
+        let (r, hs) = (BigInt::from(11), generators(1, 2));
+        let (c, s) = commit(&row, &r, &hs);
+
+        let proof = NormProof::prove(&bound, &row, &r, &hs).unwrap();
+        assert!(proof.verify(&bound, &c, &s, &hs).is_ok());
+
+        let tighter = NormBound::new(Norm::L2, BigInt::from(100), BigInt::from(4));
+        assert!(proof.verify(&tighter, &c, &s, &hs).is_err());
+        assert!(NormProof::prove(&tighter, &row, &r, &hs).is_err());
+
+        // Both cells are within the L-infinity bound, but not the L2 one.
+        let row = vec![BigInt::from(104), BigInt::from(96)];
+        assert!(NormProof::prove(&bound, &row, &r, &hs).is_err());
+        let (c, _) = commit(&row, &r, &hs);
+        assert!(proof.verify(&bound, &c, &s, &hs).is_err());
+    }
+
+    #[test]
+    fn test_linf_norm_proof() {
+        let bound = NormBound::new(Norm::LInf, BigInt::from(10), BigInt::from(4));
+        let row = vec![BigInt::from(14), BigInt::from(6), BigInt::from(10)];
+        let (r, hs) = (BigInt::from(1), generators(1, 3));
+        let (c, s) = commit(&row, &r, &hs);
+
+        let proof = NormProof::prove(&bound, &row, &r, &hs).unwrap();
+        assert!(proof.verify(&bound, &c, &s, &hs).is_ok());
+        let l2 = NormBound::new(Norm::L2, BigInt::from(10), BigInt::from(4));
+        assert!(proof.verify(&l2, &c, &s, &hs).is_err());
+        assert!(proof
+            .verify(&bound, &c, &s, &generators(2, 3)[3..])
+            .is_err());
+
+        let row = vec![BigInt::from(15), BigInt::from(6), BigInt::from(10)];
+        assert!(NormProof::prove(&bound, &row, &r, &hs).is_err());
+    }
+
+    #[test]
+    fn test_norm_bound_check() {
+        let bound = NormBound::new(Norm::L2, BigInt::zero(), DEFAULTEC.n.sqrt());
+        assert!(bound.check(1).is_ok());
+        assert!(bound.check(2).is_err());
+        assert!(NormBound::new(Norm::LInf, BigInt::zero(), BigInt::from(-1))
+            .check(1)
+            .is_err());
+    }
+}
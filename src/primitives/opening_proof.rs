@@ -0,0 +1,77 @@
+use crate::primitives::commitment::Commitment;
+use crate::primitives::ec::{random_scalar, Point};
+use crate::primitives::range_proof::{challenge, mul, reduce};
+use anyhow::anyhow;
+use num_bigint::BigInt;
+
+/// Chaum–Pedersen proof that a commitment `C = w * G + r * h` opens to `w`
+/// under the randomness committed in `S = r * H`, i.e. that
+/// `log_h(C - w * G) = log_H(S)`, without revealing `r`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OpeningProof {
+    pub(crate) e: BigInt,
+    pub(crate) z: BigInt,
+}
+
+impl OpeningProof {
+    pub fn prove(w: &BigInt, r: &BigInt, h: &Point) -> Self {
+        let c = Commitment::commit(w, r, h).c;
+        let s = Commitment::commit_randomness(r).c;
+        let x = opening_target(&c, w);
+        let k = random_scalar();
+        let (t, u) = (mul(h, &k), mul(&Point::h(), &k));
+        let e = challenge(b"opening", &[&c, &s, h, &x, &t, &u]);
+        Self {
+            z: reduce(&(k + &e * r)),
+            e,
+        }
+    }
+
+    pub fn verify(
+        &self,
+        commitment: &Commitment,
+        randomness_commitment: &Commitment,
+        h: &Point,
+        w: &BigInt,
+    ) -> anyhow::Result<()> {
+        let (c, s) = (&commitment.c, &randomness_commitment.c);
+        let x = opening_target(c, w);
+        let e = &-&self.e;
+        let t = &mul(h, &self.z) + &mul(&x, e);
+        let u = &mul(&Point::h(), &self.z) + &mul(s, e);
+        if challenge(b"opening", &[c, s, h, &x, &t, &u]) != self.e {
+            return Err(anyhow!("Invalid opening proof."));
+        }
+        Ok(())
+    }
+}
+
+/// `C - w * G`, which equals `r * h` for an honest opening.
+fn opening_target(c: &Point, w: &BigInt) -> Point {
+    c + &mul(&Point::default(), &-w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::commitment::generator;
+
+    #[test]
+    fn test_opening_proof() {
+        let (w, r) = (BigInt::from(42), BigInt::from(1234567));
+        let h = generator(0, 0);
+        let c = Commitment::commit(&w, &r, &h);
+        let s = Commitment::commit_randomness(&r);
+
+        let proof = OpeningProof::prove(&w, &r, &h);
+        assert!(proof.verify(&c, &s, &h, &w).is_ok());
+        assert!(proof.verify(&c, &s, &h, &BigInt::from(43)).is_err());
+        assert!(proof.verify(&c, &s, &generator(0, 1), &w).is_err());
+
+        // A wrong value cannot be proven for the same commitments.
+        let other = Commitment::commit(&BigInt::from(43), &(&r - 1), &h);
+        assert!(proof.verify(&other, &s, &h, &BigInt::from(43)).is_err());
+        let forged = OpeningProof::prove(&BigInt::from(43), &(&r - 1), &h);
+        assert!(forged.verify(&c, &s, &h, &BigInt::from(43)).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::primitives::commitment::Commitment;
+use crate::primitives::ec::{random_scalar, Point, DEFAULTEC};
+use crate::util::codec::Encode;
+use anyhow::anyhow;
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, Zero};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+/// Proof that `commitment = b * G + rho * H` with `b` in {0, 1}, as an OR of
+/// two Schnorr proofs on `H`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BitProof {
+    pub(crate) commitment: Point,
+    pub(crate) e0: BigInt,
+    pub(crate) e1: BigInt,
+    pub(crate) s0: BigInt,
+    pub(crate) s1: BigInt,
+}
+
+/// Bit decompositions of `v - min` and `max - v` for a committed `v`, tied
+/// together by a Schnorr proof that they add up to `max - min`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BitDecomposition {
+    pub(crate) lower: Vec<BitProof>,
+    pub(crate) upper: Vec<BitProof>,
+    pub(crate) sum_e: BigInt,
+    pub(crate) sum_z: BigInt,
+}
+
+/// Proof that the value `w` of a commitment `C = w * G + r * h`, whose
+/// randomness is committed in `S = r * H`, lies in `[min, max]`.
+///
+/// The bit decomposition of `w - min` is tied to `(C, S)` by a proof of
+/// knowledge of `r` and of the bit commitments' combined randomness.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RangeProof {
+    pub(crate) bits: BitDecomposition,
+    pub(crate) link_e: BigInt,
+    pub(crate) link_r: BigInt,
+    pub(crate) link_rho: BigInt,
+}
+
+/// Proof that a Pedersen commitment `P = v * G + sigma * H` opens to a value
+/// in `[min, max]`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PedersenRangeProof {
+    pub(crate) bits: BitDecomposition,
+    pub(crate) link_e: BigInt,
+    pub(crate) link_z: BigInt,
+}
+
+impl BitDecomposition {
+    /// Returns the proof, `sum_i 2^i * B_i` over the lower bits and the
+    /// randomness of that sum.
+    fn prove(v: &BigInt, min: &BigInt, max: &BigInt, ctx: &[&Point]) -> (Self, Point, BigInt) {
+        let n_bits = n_bits(min, max);
+        let (lower, rho_lower) = prove_bits(&(v - min), n_bits, ctx);
+        let (upper, rho_upper) = prove_bits(&(max - v), n_bits, ctx);
+        let sum_lower = weighted_sum(&lower);
+        let sum_upper = weighted_sum(&upper);
+
+        // sum_lower + sum_upper - (max - min) * G = (rho_lower + rho_upper) * H
+        let y = &(&sum_lower + &sum_upper) + &mul(&Point::default(), &(min - max));
+        let k = random_scalar();
+        let t = mul(&Point::h(), &k);
+        let sum_e = challenge(b"sum", &[ctx, &[&y, &t]].concat());
+        let sum_z = reduce(&(k + &sum_e * (&rho_lower + &rho_upper)));
+
+        let proof = Self {
+            lower,
+            upper,
+            sum_e,
+            sum_z,
+        };
+        (proof, sum_lower, rho_lower)
+    }
+
+    /// Returns `sum_i 2^i * B_i` over the lower bits.
+    fn verify(&self, min: &BigInt, max: &BigInt, ctx: &[&Point]) -> anyhow::Result<Point> {
+        let n_bits = n_bits(min, max);
+        if self.lower.len() != n_bits || self.upper.len() != n_bits {
+            return Err(anyhow!("Range proof has the wrong number of bits."));
+        }
+        self.lower
+            .par_iter()
+            .chain(self.upper.par_iter())
+            .try_for_each(|el| el.verify(ctx))?;
+        let sum_lower = weighted_sum(&self.lower);
+        let sum_upper = weighted_sum(&self.upper);
+
+        let y = &(&sum_lower + &sum_upper) + &mul(&Point::default(), &(min - max));
+        let t = &mul(&Point::h(), &self.sum_z) + &mul(&y, &-&self.sum_e);
+        if challenge(b"sum", &[ctx, &[&y, &t]].concat()) != self.sum_e {
+            return Err(anyhow!("Range proof bit decompositions do not match."));
+        }
+        Ok(sum_lower)
+    }
+}
+
+impl RangeProof {
+    pub fn prove(
+        w: &BigInt,
+        r: &BigInt,
+        h: &Point,
+        min: &BigInt,
+        max: &BigInt,
+    ) -> anyhow::Result<Self> {
+        if w < min || w > max {
+            return Err(anyhow!("Value {} out of bounds [{}, {}].", w, min, max));
+        }
+        let c = Commitment::commit(w, r, h).c;
+        let s = Commitment::commit_randomness(r).c;
+        let (bits, sum_lower, rho_lower) = BitDecomposition::prove(w, min, max, &[&c, &s, h]);
+
+        // C - min * G - sum_lower = r * h - rho_lower * H, with S = r * H
+        let x = &(&c + &mul(&Point::default(), &-min)) + &(-sum_lower);
+        let (k_r, k_rho) = (random_scalar(), random_scalar());
+        let t1 = &mul(h, &k_r) + &mul(&Point::h(), &-&k_rho);
+        let t2 = mul(&Point::h(), &k_r);
+        let link_e = challenge(b"link", &[&c, &s, h, &x, &t1, &t2]);
+        let link_r = reduce(&(k_r + &link_e * r));
+        let link_rho = reduce(&(k_rho + &link_e * rho_lower));
+
+        Ok(Self {
+            bits,
+            link_e,
+            link_r,
+            link_rho,
+        })
+    }
+
+    pub fn verify(
+        &self,
+        commitment: &Commitment,
+        randomness_commitment: &Commitment,
+        h: &Point,
+        min: &BigInt,
+        max: &BigInt,
+    ) -> anyhow::Result<()> {
+        let (c, s) = (&commitment.c, &randomness_commitment.c);
+        let sum_lower = self.bits.verify(min, max, &[c, s, h])?;
+
+        let x = &(c + &mul(&Point::default(), &-min)) + &(-sum_lower);
+        let t1 = &(&mul(h, &self.link_r) + &mul(&Point::h(), &-&self.link_rho))
+            + &mul(&x, &-&self.link_e);
+        let t2 = &mul(&Point::h(), &self.link_r) + &mul(s, &-&self.link_e);
+        if challenge(b"link", &[c, s, h, &x, &t1, &t2]) != self.link_e {
+            return Err(anyhow!("Range proof does not match the commitment."));
+        }
+        Ok(())
+    }
+}
+
+impl PedersenRangeProof {
+    pub fn prove(v: &BigInt, sigma: &BigInt, min: &BigInt, max: &BigInt) -> anyhow::Result<Self> {
+        if v < min || v > max {
+            return Err(anyhow!("Value {} out of bounds [{}, {}].", v, min, max));
+        }
+        let p = pedersen(v, sigma);
+        let (bits, sum_lower, rho_lower) = BitDecomposition::prove(v, min, max, &[&p]);
+
+        // P - min * G - sum_lower = (sigma - rho_lower) * H
+        let x = &(&p + &mul(&Point::default(), &-min)) + &(-sum_lower);
+        let k = random_scalar();
+        let t = mul(&Point::h(), &k);
+        let link_e = challenge(b"pedersen", &[&p, &x, &t]);
+        let link_z = reduce(&(k + &link_e * (sigma - rho_lower)));
+
+        Ok(Self {
+            bits,
+            link_e,
+            link_z,
+        })
+    }
+
+    pub fn verify(&self, commitment: &Point, min: &BigInt, max: &BigInt) -> anyhow::Result<()> {
+        let sum_lower = self.bits.verify(min, max, &[commitment])?;
+        let x = &(commitment + &mul(&Point::default(), &-min)) + &(-sum_lower);
+        let t = &mul(&Point::h(), &self.link_z) + &mul(&x, &-&self.link_e);
+        if challenge(b"pedersen", &[commitment, &x, &t]) != self.link_e {
+            return Err(anyhow!("Range proof does not match the commitment."));
+        }
+        Ok(())
+    }
+}
+
+impl BitProof {
+    fn prove(bit: bool, ctx: &[&Point]) -> (Self, BigInt) {
+        let rho = random_scalar();
+        let commitment =
+            &mul(&Point::default(), &BigInt::from(bit as u8)) + &mul(&Point::h(), &rho);
+        let targets = bit_targets(&commitment);
+
+        // Simulate the branch for the other bit, prove the real one.
+        let (e_fake, s_fake) = (random_scalar(), random_scalar());
+        let fake = &targets[!bit as usize];
+        let t_fake = &mul(&Point::h(), &s_fake) + &mul(fake, &-&e_fake);
+        let k = random_scalar();
+        let t_real = mul(&Point::h(), &k);
+
+        let (t0, t1) = if bit {
+            (t_fake, t_real)
+        } else {
+            (t_real, t_fake)
+        };
+        let e = challenge(b"bit", &[ctx, &[&commitment, &t0, &t1]].concat());
+        let e_real = reduce(&(e - &e_fake));
+        let s_real = reduce(&(k + &e_real * &rho));
+
+        let (e0, e1, s0, s1) = if bit {
+            (e_fake, e_real, s_fake, s_real)
+        } else {
+            (e_real, e_fake, s_real, s_fake)
+        };
+        let proof = Self {
+            commitment,
+            e0,
+            e1,
+            s0,
+            s1,
+        };
+        (proof, rho)
+    }
+
+    fn verify(&self, ctx: &[&Point]) -> anyhow::Result<()> {
+        let targets = bit_targets(&self.commitment);
+        let t0 = &mul(&Point::h(), &self.s0) + &mul(&targets[0], &-&self.e0);
+        let t1 = &mul(&Point::h(), &self.s1) + &mul(&targets[1], &-&self.e1);
+        let e = challenge(b"bit", &[ctx, &[&self.commitment, &t0, &t1]].concat());
+        if reduce(&(&self.e0 + &self.e1)) != e {
+            return Err(anyhow!("Invalid bit proof."));
+        }
+        Ok(())
+    }
+}
+
+/// `B` for a 0 bit and `B - G` for a 1 bit: the point that must be a multiple
+/// of `H` in each branch.
+fn bit_targets(commitment: &Point) -> [Point; 2] {
+    [commitment.clone(), commitment + &(-Point::default())]
+}
+
+fn prove_bits(value: &BigInt, n_bits: usize, ctx: &[&Point]) -> (Vec<BitProof>, BigInt) {
+    let proofs: Vec<(BitProof, BigInt)> = (0..n_bits)
+        .into_par_iter()
+        .map(|i| BitProof::prove(value.bit(i as u64), ctx))
+        .collect();
+    let rho = proofs
+        .iter()
+        .rev()
+        .fold(BigInt::zero(), |acc, (_, rho)| acc * 2 + rho);
+    (
+        proofs.into_iter().map(|(proof, _)| proof).collect(),
+        reduce(&rho),
+    )
+}
+
+/// `sum_i 2^i * B_i`, by Horner's rule.
+fn weighted_sum(proofs: &[BitProof]) -> Point {
+    proofs
+        .iter()
+        .rev()
+        .fold(Point::infinity(), |acc, el| &acc.double() + &el.commitment)
+}
+
+/// `v * G + sigma * H`.
+pub(crate) fn pedersen(v: &BigInt, sigma: &BigInt) -> Point {
+    &mul(&Point::default(), v) + &mul(&Point::h(), sigma)
+}
+
+fn n_bits(min: &BigInt, max: &BigInt) -> usize {
+    ((max - min).bits() as usize).max(1)
+}
+
+pub(crate) fn mul(point: &Point, scalar: &BigInt) -> Point {
+    point.clone() * &reduce(scalar)
+}
+
+pub(crate) fn reduce(scalar: &BigInt) -> BigInt {
+    scalar.modpow(&BigInt::one(), &DEFAULTEC.n)
+}
+
+pub(crate) fn challenge(label: &[u8], points: &[&Point]) -> BigInt {
+    let mut hasher = Sha256::new()
+        .chain_update(b"viper-range-proof")
+        .chain_update(label);
+    for point in points {
+        hasher.update(point.to_bytes());
+    }
+    reduce(&BigInt::from_bytes_le(Sign::Plus, &hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::commitment::generator;
+
+    #[test]
+    fn test_range_proof() {
+        let (min, max) = (BigInt::from(0), BigInt::from(100));
+        let r = BigInt::from(123456);
+        let h = generator(0, 0);
+        let c = Commitment::commit(&BigInt::from(42), &r, &h);
+        let s = Commitment::commit_randomness(&r);
+
+        for w in [0, 42, 100] {
+            let w = BigInt::from(w);
+            let c = Commitment::commit(&w, &r, &h);
+            let proof = RangeProof::prove(&w, &r, &h, &min, &max).unwrap();
+            assert!(proof.verify(&c, &s, &h, &min, &max).is_ok());
+        }
+        assert!(RangeProof::prove(&BigInt::from(101), &r, &h, &min, &max).is_err());
+
+        let proof = RangeProof::prove(&BigInt::from(42), &r, &h, &min, &max).unwrap();
+        let other = Commitment::commit(&BigInt::from(43), &r, &h);
+        assert!(proof.verify(&other, &s, &h, &min, &max).is_err());
+        assert!(proof
+            .verify(
+                &c,
+                &Commitment::commit_randomness(&BigInt::from(7)),
+                &h,
+                &min,
+                &max
+            )
+            .is_err());
+        assert!(proof
+            .verify(&c, &s, &h, &BigInt::from(0), &BigInt::from(1000))
+            .is_err());
+
+        // The proof is bound to the cell generator.
+        let h1 = generator(0, 1);
+        let c1 = Commitment::commit(&BigInt::from(42), &r, &h1);
+        assert!(proof.verify(&c1, &s, &h1, &min, &max).is_err());
+    }
+
+    #[test]
+    fn test_forged_range_proof() {
+        // A proof for an in-range value cannot be reused for a commitment to
+        // an out-of-range one with the same randomness.
+        let (min, max) = (BigInt::from(10), BigInt::from(20));
+        let r = BigInt::from(99);
+        let h = generator(0, 0);
+        let s = Commitment::commit_randomness(&r);
+        let mut proof = RangeProof::prove(&BigInt::from(15), &r, &h, &min, &max).unwrap();
+
+        let c = Commitment::commit(&BigInt::from(15), &r, &h);
+        proof.bits.lower[0].commitment = &proof.bits.lower[0].commitment + &Point::default();
+        assert!(proof.verify(&c, &s, &h, &min, &max).is_err());
+
+        let c = Commitment::commit(&BigInt::from(1000), &r, &h);
+        let proof = RangeProof::prove(&BigInt::from(15), &r, &h, &min, &max).unwrap();
+        assert!(proof.verify(&c, &s, &h, &min, &max).is_err());
+    }
+
+    #[test]
+    fn test_pedersen_range_proof() {
+        let (min, max) = (BigInt::from(0), BigInt::from(50));
+        let sigma = BigInt::from(777);
+        let p = pedersen(&BigInt::from(49), &sigma);
+        let proof = PedersenRangeProof::prove(&BigInt::from(49), &sigma, &min, &max).unwrap();
+
+        assert!(proof.verify(&p, &min, &max).is_ok());
+        assert!(proof
+            .verify(&pedersen(&BigInt::from(51), &sigma), &min, &max)
+            .is_err());
+        assert!(PedersenRangeProof::prove(&BigInt::from(51), &sigma, &min, &max).is_err());
+    }
+}
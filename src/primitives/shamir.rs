@@ -0,0 +1,149 @@
+use crate::primitives::ec::{random_scalar, DEFAULTEC};
+use anyhow::anyhow;
+use num_bigint::BigInt;
+use num_traits::{One, Zero};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Share {
+    pub x: u64,
+    pub y: BigInt,
+}
+
+impl Share {
+    /// Adds two shares of the same holder, giving its share of the sum of
+    /// both secrets.
+    pub fn try_add(&self, rhs: &Share) -> anyhow::Result<Share> {
+        if self.x != rhs.x {
+            return Err(anyhow!(
+                "Shares of different holders {} and {}.",
+                self.x,
+                rhs.x
+            ));
+        }
+        Ok(Share {
+            x: self.x,
+            y: (&self.y + &rhs.y).modpow(&BigInt::one(), &DEFAULTEC.n),
+        })
+    }
+}
+
+pub fn share(secret: &BigInt, holders: &[u64], threshold: usize) -> anyhow::Result<Vec<Share>> {
+    if threshold == 0 || threshold > holders.len() {
+        return Err(anyhow!(
+            "Invalid threshold {} for {} holders.",
+            threshold,
+            holders.len()
+        ));
+    }
+    check_points(holders)?;
+
+    let coefficients: Vec<BigInt> = std::iter::once(secret.modpow(&BigInt::one(), &DEFAULTEC.n))
+        .chain((1..threshold).map(|_| random_scalar()))
+        .collect();
+
+    Ok(holders
+        .iter()
+        .map(|x| {
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(BigInt::zero(), |acc, c| acc * x + c)
+                .modpow(&BigInt::one(), &DEFAULTEC.n);
+            Share { x: *x, y }
+        })
+        .collect())
+}
+
+pub fn reconstruct(shares: &[Share], threshold: usize) -> anyhow::Result<BigInt> {
+    if threshold == 0 || shares.len() < threshold {
+        return Err(anyhow!(
+            "Not enough shares: {} of {}.",
+            shares.len(),
+            threshold
+        ));
+    }
+    let shares = &shares[..threshold];
+    check_points(&shares.iter().map(|el| el.x).collect::<Vec<u64>>())?;
+
+    let mut secret = BigInt::zero();
+    for (i, share_i) in shares.iter().enumerate() {
+        let mut num = BigInt::one();
+        let mut den = BigInt::one();
+        for (j, share_j) in shares.iter().enumerate() {
+            if i != j {
+                num *= BigInt::from(share_j.x);
+                den *= BigInt::from(share_j.x) - BigInt::from(share_i.x);
+            }
+        }
+        let den = den
+            .modpow(&BigInt::one(), &DEFAULTEC.n)
+            .modinv(&DEFAULTEC.n)
+            .ok_or_else(|| anyhow!("Share holders are not invertible."))?;
+        secret += &share_i.y * num * den;
+    }
+
+    Ok(secret.modpow(&BigInt::one(), &DEFAULTEC.n))
+}
+
+/// Holders are evaluation points mod `n`, so they must be non-zero and
+/// distinct mod `n`, not just as ids.
+fn check_points(xs: &[u64]) -> anyhow::Result<()> {
+    let points: Vec<BigInt> = xs
+        .iter()
+        .map(|x| BigInt::from(*x).modpow(&BigInt::one(), &DEFAULTEC.n))
+        .collect();
+    for (i, (x, point)) in xs.iter().zip(points.iter()).enumerate() {
+        if point.is_zero() {
+            return Err(anyhow!("Invalid share holder {}.", x));
+        }
+        if points[..i].contains(point) {
+            return Err(anyhow!("Duplicated share holder {}.", x));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_share_reconstruct() {
+        let secret = BigInt::from(1234);
+        let shares = share(&secret, &[1, 2, 3, 4, 5], 3).unwrap();
+
+        assert_eq!(reconstruct(&shares[2..], 3).unwrap(), secret);
+        assert_eq!(
+            reconstruct(
+                &[shares[4].clone(), shares[0].clone(), shares[2].clone()],
+                3
+            )
+            .unwrap(),
+            secret
+        );
+        assert!(reconstruct(&shares[..2], 3).is_err());
+    }
+
+    #[test]
+    fn test_add_shares() {
+        let holders = [1, 2, 3];
+        let s1 = share(&BigInt::from(10), &holders, 2).unwrap();
+        let s2 = share(&BigInt::from(32), &holders, 2).unwrap();
+
+        let sum: Vec<Share> = s1
+            .iter()
+            .zip(s2.iter())
+            .map(|(a, b)| a.try_add(b).unwrap())
+            .collect();
+        assert_eq!(reconstruct(&sum[1..], 2).unwrap(), BigInt::from(42));
+        assert!(s1[0].try_add(&s2[1]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_share() {
+        let secret = BigInt::from(7);
+        assert!(share(&secret, &[1, 2], 3).is_err());
+        assert!(share(&secret, &[0, 1, 2], 2).is_err());
+        assert!(share(&secret, &[1, 1, 2], 2).is_err());
+    }
+}
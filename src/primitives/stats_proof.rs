@@ -0,0 +1,263 @@
+use crate::primitives::commitment::Commitment;
+use crate::primitives::ec::{random_scalar, Point};
+use crate::primitives::norm_proof::SquareProof;
+use crate::primitives::range_proof::{challenge, mul, reduce, RangeProof};
+use anyhow::anyhow;
+use num_bigint::BigInt;
+use rayon::prelude::*;
+
+/// Constraint `min <= sum_i a_i * w_i + b <= max` over the cells `w_i` of a
+/// committed row. Commitments are additively homomorphic, so the combination
+/// `sum_i a_i * C_i + b * G` is itself a commitment under the generator
+/// `sum_i a_i * H_i` with the row's randomness, and a [`RangeProof`] on it
+/// proves the constraint.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinearRange {
+    pub terms: Vec<(usize, BigInt)>,
+    pub constant: BigInt,
+    pub min: BigInt,
+    pub max: BigInt,
+}
+
+/// Proof that the cell `y` of a committed row is the square of the cell `x`:
+/// a [`SquareProof`] that `Q` is a Pedersen commitment to `x^2`, and a proof
+/// that `Q` and the commitment of `y` are over the same value, i.e. that
+/// `Q - C_y = sigma * H - r * H_y` with `S = r * H`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SquareLinkProof {
+    pub(crate) square: SquareProof,
+    pub(crate) e: BigInt,
+    pub(crate) z_sigma: BigInt,
+    pub(crate) z_r: BigInt,
+}
+
+/// Proof that one committed row satisfies a set of square links and linear
+/// ranges, e.g. those of [`crate::system::stats::StatsEncoder`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StatsProof {
+    pub(crate) squares: Vec<SquareLinkProof>,
+    pub(crate) ranges: Vec<RangeProof>,
+}
+
+impl LinearRange {
+    pub fn new(terms: Vec<(usize, BigInt)>, constant: BigInt, min: BigInt, max: BigInt) -> Self {
+        Self {
+            terms,
+            constant,
+            min,
+            max,
+        }
+    }
+
+    pub fn prove(
+        &self,
+        row: &[BigInt],
+        randomness: &BigInt,
+        generators: &[Point],
+    ) -> anyhow::Result<RangeProof> {
+        self.check(row.len())?;
+        let value = self
+            .terms
+            .iter()
+            .fold(self.constant.clone(), |acc, (i, a)| acc + a * &row[*i]);
+        RangeProof::prove(
+            &value,
+            randomness,
+            &self.combine(generators, Point::infinity()),
+            &self.min,
+            &self.max,
+        )
+    }
+
+    pub fn verify(
+        &self,
+        proof: &RangeProof,
+        commitments: &[Commitment],
+        randomness_commitment: &Commitment,
+        generators: &[Point],
+    ) -> anyhow::Result<()> {
+        self.check(commitments.len())?;
+        let c = self.combine(
+            &commitments
+                .iter()
+                .map(|el| el.c.clone())
+                .collect::<Vec<_>>(),
+            mul(&Point::default(), &self.constant),
+        );
+        proof.verify(
+            &Commitment::new(c),
+            randomness_commitment,
+            &self.combine(generators, Point::infinity()),
+            &self.min,
+            &self.max,
+        )
+    }
+
+    /// `init + sum_i a_i * points_i`.
+    fn combine(&self, points: &[Point], init: Point) -> Point {
+        self.terms
+            .iter()
+            .fold(init, |acc, (i, a)| &acc + &mul(&points[*i], a))
+    }
+
+    fn check(&self, len: usize) -> anyhow::Result<()> {
+        if self.terms.iter().any(|(i, _)| *i >= len) {
+            return Err(anyhow!("Constraint refers to a cell past the row."));
+        }
+        Ok(())
+    }
+}
+
+impl SquareLinkProof {
+    /// `h_x` and `h_y` are the generators of the cells of `x` and `x^2`.
+    pub fn prove(x: &BigInt, r: &BigInt, h_x: &Point, h_y: &Point) -> Self {
+        let (square, sigma) = SquareProof::prove(x, r, h_x, &BigInt::from(0));
+        let c_x = Commitment::commit(x, r, h_x).c;
+        let c_y = Commitment::commit(&x.pow(2), r, h_y).c;
+        let s = Commitment::commit_randomness(r).c;
+
+        let (k_sigma, k_r) = (random_scalar(), random_scalar());
+        let t1 = &mul(&Point::h(), &k_sigma) + &mul(h_y, &-&k_r);
+        let t2 = mul(&Point::h(), &k_r);
+        let e = challenge(
+            b"square-link",
+            &[&c_x, &c_y, &s, h_x, h_y, &square.big_q, &t1, &t2],
+        );
+        Self {
+            z_sigma: reduce(&(k_sigma + &e * sigma)),
+            z_r: reduce(&(k_r + &e * r)),
+            square,
+            e,
+        }
+    }
+
+    /// `x` and `y` are the commitments of the two cells with their
+    /// generators, and `s` the row's randomness commitment.
+    pub fn verify(
+        &self,
+        x: (&Point, &Point),
+        y: (&Point, &Point),
+        s: &Point,
+    ) -> anyhow::Result<()> {
+        let ((c_x, h_x), (c_y, h_y)) = (x, y);
+        self.square.verify(&BigInt::from(0), c_x, s, h_x)?;
+        let e = &-&self.e;
+        let diff = &self.square.big_q + &(-c_y.clone());
+        let t1 = &(&mul(&Point::h(), &self.z_sigma) + &mul(h_y, &-&self.z_r)) + &mul(&diff, e);
+        let t2 = &mul(&Point::h(), &self.z_r) + &mul(s, e);
+        let expected = challenge(
+            b"square-link",
+            &[c_x, c_y, s, h_x, h_y, &self.square.big_q, &t1, &t2],
+        );
+        if expected != self.e {
+            return Err(anyhow!("Cell is not the square of its linked cell."));
+        }
+        Ok(())
+    }
+}
+
+impl StatsProof {
+    /// Proves that `row[y] = row[x]^2` for every `(x, y)` in `squares` and
+    /// that `row` satisfies every constraint in `ranges`.
+    pub fn prove(
+        squares: &[(usize, usize)],
+        ranges: &[LinearRange],
+        row: &[BigInt],
+        randomness: &BigInt,
+        generators: &[Point],
+    ) -> anyhow::Result<Self> {
+        if row.len() != generators.len() || squares.iter().any(|(x, y)| *x.max(y) >= row.len()) {
+            return Err(anyhow!("Row does not match the constraints."));
+        }
+        Ok(Self {
+            squares: squares
+                .par_iter()
+                .map(|(x, y)| {
+                    SquareLinkProof::prove(&row[*x], randomness, &generators[*x], &generators[*y])
+                })
+                .collect(),
+            ranges: ranges
+                .par_iter()
+                .map(|el| el.prove(row, randomness, generators))
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    pub fn verify(
+        &self,
+        squares: &[(usize, usize)],
+        ranges: &[LinearRange],
+        commitments: &[Commitment],
+        randomness_commitment: &Commitment,
+        generators: &[Point],
+    ) -> anyhow::Result<()> {
+        let n = commitments.len();
+        if generators.len() != n
+            || self.squares.len() != squares.len()
+            || self.ranges.len() != ranges.len()
+            || squares.iter().any(|(x, y)| *x.max(y) >= n)
+        {
+            return Err(anyhow!("Statistics proof does not match the row."));
+        }
+        self.squares
+            .par_iter()
+            .zip(squares)
+            .try_for_each(|(proof, (x, y))| {
+                proof.verify(
+                    (&commitments[*x].c, &generators[*x]),
+                    (&commitments[*y].c, &generators[*y]),
+                    &randomness_commitment.c,
+                )
+            })?;
+        self.ranges
+            .par_iter()
+            .zip(ranges)
+            .try_for_each(|(proof, range)| {
+                range.verify(proof, commitments, randomness_commitment, generators)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::commitment::generators;
+
+    fn commit(row: &[BigInt], r: &BigInt, hs: &[Point]) -> (Vec<Commitment>, Commitment) {
+        (
+            row.iter()
+                .zip(hs)
+                .map(|(w, h)| Commitment::commit(w, r, h))
+                .collect(),
+            Commitment::commit_randomness(r),
+        )
+    }
+
+    #[test]
+    fn test_stats_proof() {
+        // (x, x^2, 1 - x)
+        let squares = [(0, 1)];
+        let ranges = [LinearRange::new(
+            vec![(0, BigInt::from(1)), (2, BigInt::from(1))],
+            BigInt::from(0),
+            BigInt::from(1),
+            BigInt::from(1),
+        )];
+        let (r, hs) = (BigInt::from(11), generators(1, 3));
+
+        let row = vec![BigInt::from(1), BigInt::from(1), BigInt::from(0)];
+        let (c, s) = commit(&row, &r, &hs);
+        let proof = StatsProof::prove(&squares, &ranges, &row, &r, &hs).unwrap();
+        assert!(proof.verify(&squares, &ranges, &c, &s, &hs).is_ok());
+
+        // The proof does not carry over to other values.
+        let other = vec![BigInt::from(1), BigInt::from(2), BigInt::from(0)];
+        let (c, s) = commit(&other, &r, &hs);
+        assert!(proof.verify(&squares, &ranges, &c, &s, &hs).is_err());
+        let forged = StatsProof::prove(&squares, &ranges, &other, &r, &hs).unwrap();
+        assert!(forged.verify(&squares, &ranges, &c, &s, &hs).is_err());
+
+        let other = vec![BigInt::from(1), BigInt::from(1), BigInt::from(1)];
+        assert!(StatsProof::prove(&squares, &ranges, &other, &r, &hs).is_err());
+    }
+}
@@ -1,47 +1,652 @@
-use crate::primitives::commitment::Commitment;
-use crate::primitives::ec::Point;
+use crate::primitives::commitment::{derive_randomness, generators, Commitment, CommitmentMatrix};
+use crate::primitives::consistency_proof::ConsistencyProof;
+use crate::primitives::dlog::DlogTable;
 use crate::primitives::mkhs::{Mkhs, Signature};
-use crate::primitives::phollard_rho::pollards_rho;
+use crate::primitives::norm_proof::{NormBound, NormProof};
+use crate::primitives::opening_proof::OpeningProof;
+use crate::primitives::range_proof::RangeProof;
+use crate::primitives::shamir::{self, Share};
+use crate::system::masking;
+use crate::util::schema::{Schema, Table};
+use anyhow::anyhow;
+use ark_bls12_381::Fr;
 use num_bigint::BigInt;
+use num_traits::Zero;
 use rayon::prelude::*;
+use std::collections::BTreeSet;
 use std::ops::Neg;
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AggregationResult {
+    pub commitments: CommitmentMatrix,
+    pub randomness_commitment: Commitment,
+    pub signature: Signature,
+    pub table: Table,
+    pub opening_proofs: Vec<OpeningProof>,
+}
+
+impl AggregationResult {
+    pub fn fr(&self) -> Vec<Vec<Fr>> {
+        self.table.fr()
+    }
+
+    /// Checks that every cell of the table opens its aggregated commitment,
+    /// without needing the aggregated randomness.
+    pub fn verify_openings(&self) -> anyhow::Result<()> {
+        let cols = self.commitments.cols;
+        self.commitments.check_shape(self.table.rows.len(), cols)?;
+        if self.table.rows.iter().any(|row| row.len() != cols)
+            || self.opening_proofs.len() != self.commitments.data.len()
+        {
+            return Err(anyhow!("Opening proofs do not match the table shape."));
+        }
+        self.opening_proofs
+            .par_iter()
+            .zip(self.commitments.generators())
+            .enumerate()
+            .try_for_each(|(i, (proof, h))| {
+                proof
+                    .verify(
+                        &self.commitments.data[i],
+                        &self.randomness_commitment,
+                        &h,
+                        &self.table.rows[i / cols][i % cols],
+                    )
+                    .map_err(|e| anyhow!("Cell ({}, {}): {}", i / cols, i % cols, e))
+            })
+    }
+}
+
+/// Sums of the contributions of a subtree of clients, forwarded by an
+/// intermediate aggregator to its parent. The masked randomness of the
+/// subtree is summed too: masks only cancel once the root has combined
+/// every client.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PartialAggregate {
+    pub clients: BTreeSet<u64>,
+    pub commitments: CommitmentMatrix,
+    pub randomness_commitment: Commitment,
+    pub signature: Signature,
+    pub masked_randomness: BigInt,
+}
+
+impl PartialAggregate {
+    /// The contribution of a single client, once its proofs are verified.
+    pub fn leaf(
+        client_id: u64,
+        commitments: CommitmentMatrix,
+        randomness_commitment: Commitment,
+        signature: Signature,
+        masked_randomness: BigInt,
+    ) -> Self {
+        Self {
+            clients: BTreeSet::from([client_id]),
+            commitments,
+            randomness_commitment,
+            signature,
+            masked_randomness,
+        }
+    }
+}
+
 pub struct Aggregator;
 
 impl Aggregator {
-    pub fn aggregate_commitments(commitments: &[Vec<Commitment>]) -> Vec<Commitment> {
-        let commitments_t: Vec<Vec<Commitment>> = transpose_dataset(commitments);
-        commitments_t
-            .par_iter()
-            .map(|col| col.par_iter().map(|el| el.clone()).sum::<Commitment>())
-            .collect()
+    pub fn aggregate(
+        mkhs: &Mkhs,
+        schema: &Schema,
+        commitments: &[CommitmentMatrix],
+        signatures: &[Signature],
+        randomness_commitments: &[Commitment],
+        randomness: &BigInt,
+    ) -> anyhow::Result<AggregationResult> {
+        let n_client = commitments.len();
+        if randomness_commitments.len() != n_client {
+            return Err(anyhow!(
+                "Expected {} randomness commitments, got {}.",
+                n_client,
+                randomness_commitments.len()
+            ));
+        }
+        let (commitments, signature) = rayon::join(
+            || Self::aggregate_commitments(commitments),
+            || Self::aggregate_signatures(mkhs, signatures),
+        );
+        Self::finish(
+            schema,
+            commitments?,
+            randomness_commitments.iter().cloned().sum(),
+            signature?,
+            randomness,
+            n_client,
+        )
+    }
+
+    /// Combines the partial aggregates of disjoint subtrees into the
+    /// partial aggregate of their union.
+    pub fn combine(mkhs: &Mkhs, partials: &[PartialAggregate]) -> anyhow::Result<PartialAggregate> {
+        let mut clients = BTreeSet::new();
+        for partial in partials {
+            if let Some(id) = partial.clients.intersection(&clients).next() {
+                return Err(anyhow!("Client {} is in several subtrees.", id));
+            }
+            clients.extend(partial.clients.iter().copied());
+        }
+        let commitments = Self::aggregate_commitments(
+            &partials
+                .iter()
+                .map(|el| el.commitments.clone())
+                .collect::<Vec<_>>(),
+        )?;
+        let randomness_commitment = partials
+            .iter()
+            .map(|el| el.randomness_commitment.clone())
+            .sum();
+        let signature = Self::aggregate_signatures(
+            mkhs,
+            &partials
+                .iter()
+                .map(|el| el.signature.clone())
+                .collect::<Vec<_>>(),
+        )?;
+        let masked: Vec<BigInt> = partials
+            .iter()
+            .map(|el| el.masked_randomness.clone())
+            .collect();
+        Ok(PartialAggregate {
+            clients,
+            commitments,
+            randomness_commitment,
+            signature,
+            masked_randomness: masking::unmask(&masked),
+        })
     }
 
-    pub fn aggregate_signatures(mkhs: &Mkhs, signatures: &[Vec<Signature>]) -> Vec<Signature> {
-        let signatures_t: Vec<Vec<Signature>> = transpose_dataset(signatures);
-        signatures_t.par_iter().map(|col| mkhs.eval(col)).collect()
+    /// Opens the partial aggregate of the root, which covers every client so
+    /// its randomness is unmasked. The result verifies as in the flat case.
+    pub fn finalize(schema: &Schema, root: &PartialAggregate) -> anyhow::Result<AggregationResult> {
+        Self::finish(
+            schema,
+            root.commitments.clone(),
+            root.randomness_commitment.clone(),
+            root.signature.clone(),
+            &root.masked_randomness,
+            root.clients.len(),
+        )
     }
 
-    pub fn open_commitments(
-        commitments: &[Commitment],
-        secret: &BigInt,
-    ) -> anyhow::Result<Vec<BigInt>> {
-        let commitments: Vec<Commitment> = commitments
+    fn finish(
+        schema: &Schema,
+        commitments: CommitmentMatrix,
+        randomness_commitment: Commitment,
+        signature: Signature,
+        randomness: &BigInt,
+        n_client: usize,
+    ) -> anyhow::Result<AggregationResult> {
+        // The aggregated randomness must be the sum of the ones the clients
+        // committed to, which fixes the value opened in every cell.
+        randomness_commitment.open_randomness(randomness)?;
+
+        let table = Self::open_table(&commitments, randomness, schema, n_client)?;
+        let opening_proofs = Self::prove_openings(&table.rows, randomness)?;
+        Ok(AggregationResult {
+            commitments,
+            randomness_commitment,
+            signature,
+            table,
+            opening_proofs,
+        })
+    }
+
+    /// Checks one client's range proofs against its commitments and the
+    /// column bounds of `schema`, before its contribution is included.
+    pub fn verify_range_proofs(
+        schema: &Schema,
+        commitments: &CommitmentMatrix,
+        randomness_commitment: &Commitment,
+        range_proofs: &[RangeProof],
+    ) -> anyhow::Result<()> {
+        commitments.check_shape(commitments.rows, schema.len())?;
+        if range_proofs.len() != commitments.data.len() {
+            return Err(anyhow!(
+                "Expected {} range proofs, got {}.",
+                commitments.data.len(),
+                range_proofs.len()
+            ));
+        }
+        range_proofs
             .par_iter()
-            .map(|el| Commitment::new(&el.c + &(Point::default() * secret).neg()))
-            .collect();
+            .zip(commitments.generators())
+            .enumerate()
+            .try_for_each(|(i, (proof, h))| {
+                let column = &schema.columns[i % schema.len()];
+                proof
+                    .verify(
+                        &commitments.data[i],
+                        randomness_commitment,
+                        &h,
+                        &column.min,
+                        &column.max,
+                    )
+                    .map_err(|e| {
+                        anyhow!("Cell ({}, {}): {}", i / schema.len(), i % schema.len(), e)
+                    })
+            })
+    }
+
+    /// Checks that every row committed by one client has a norm within
+    /// `bound`, so a single client cannot inflate its influence.
+    pub fn verify_norm_proofs(
+        bound: &NormBound,
+        commitments: &CommitmentMatrix,
+        randomness_commitment: &Commitment,
+        norm_proofs: &[NormProof],
+    ) -> anyhow::Result<()> {
+        if norm_proofs.len() != commitments.rows {
+            return Err(anyhow!(
+                "Expected {} norm proofs, got {}.",
+                commitments.rows,
+                norm_proofs.len()
+            ));
+        }
+        let generators = commitments.generators();
+        norm_proofs
+            .par_iter()
+            .zip(generators.par_chunks(commitments.cols.max(1)))
+            .enumerate()
+            .try_for_each(|(i, (proof, hs))| {
+                proof
+                    .verify(
+                        bound,
+                        commitments.row(i).unwrap(),
+                        randomness_commitment,
+                        hs,
+                    )
+                    .map_err(|e| anyhow!("Row {}: {}", i, e))
+            })
+    }
+
+    /// Checks that the dataset signature of client `client_id` is its own
+    /// and over the dataset it committed to, under `schema`.
+    pub fn verify_consistency_proof(
+        mkhs: &Mkhs,
+        client_id: u64,
+        schema: &Schema,
+        commitments: &CommitmentMatrix,
+        randomness_commitment: &Commitment,
+        signature: &Signature,
+        consistency_proof: &ConsistencyProof,
+    ) -> anyhow::Result<()> {
+        consistency_proof.verify(
+            mkhs,
+            client_id,
+            &schema.digest(),
+            signature,
+            commitments,
+            randomness_commitment,
+        )
+    }
+
+    pub fn aggregate_commitments(
+        commitments: &[CommitmentMatrix],
+    ) -> anyhow::Result<CommitmentMatrix> {
+        let (rows, cols) = commitments
+            .first()
+            .ok_or_else(|| anyhow!("No commitments to aggregate."))?
+            .shape();
         commitments
+            .iter()
+            .try_for_each(|el| el.check_shape(rows, cols))?;
+
+        let data = (0..rows * cols)
+            .into_par_iter()
+            .map(|i| commitments.iter().map(|el| el.data[i].clone()).sum())
+            .collect();
+        CommitmentMatrix::new(rows, cols, data)
+    }
+
+    /// Combines the dataset signatures of the clients into one signature on
+    /// the aggregated dataset.
+    pub fn aggregate_signatures(
+        mkhs: &Mkhs,
+        signatures: &[Signature],
+    ) -> anyhow::Result<Signature> {
+        if signatures.is_empty() {
+            return Err(anyhow!("No signatures to aggregate."));
+        }
+        Ok(mkhs.eval(signatures))
+    }
+
+    pub fn unmask_randomness(masked_randomness: &[BigInt]) -> BigInt {
+        masking::unmask(masked_randomness)
+    }
+
+    pub fn recover_randomness(
+        partial_randomness: &BigInt,
+        missing_shares: &[Vec<Share>],
+        threshold: usize,
+        round: u64,
+    ) -> anyhow::Result<BigInt> {
+        let mut randomness = missing_shares
             .par_iter()
-            .map(|el| pollards_rho(&Point::default(), &el.c))
+            .map(|shares| {
+                let seed = shamir::reconstruct(shares, threshold)?;
+                Ok(derive_randomness(&seed, round))
+            })
+            .collect::<anyhow::Result<Vec<BigInt>>>()?;
+        randomness.push(partial_randomness.clone());
+        Ok(masking::unmask(&randomness))
+    }
+
+    /// Opens every cell by a bounded discrete log, `bounds[j]` being the
+    /// largest value the aggregate can hold in column `j`.
+    pub fn open_commitments(
+        commitments: &CommitmentMatrix,
+        randomness: &BigInt,
+        bounds: &[BigInt],
+    ) -> anyhow::Result<Vec<Vec<BigInt>>> {
+        if bounds.len() != commitments.cols {
+            return Err(anyhow!("Expected {} column bounds.", commitments.cols));
+        }
+        let table = DlogTable::new(bounds.iter().max().unwrap_or(&BigInt::zero()))?;
+        let generators = commitments.generators();
+        commitments
+            .data
+            .par_chunks(commitments.cols.max(1))
+            .zip(generators.par_chunks(commitments.cols.max(1)))
+            .map(|(row, hs)| {
+                row.par_iter()
+                    .zip(hs)
+                    .zip(bounds)
+                    .map(|((el, h), bound)| {
+                        let h_r = (h.clone() * randomness).neg();
+                        table.solve(&(&el.c + &h_r), bound)
+                    })
+                    .collect()
+            })
             .collect()
     }
+
+    /// Proves every opened value against its commitment, so the result can
+    /// be audited by parties that do not hold the aggregated randomness.
+    pub fn prove_openings(
+        values: &[Vec<BigInt>],
+        randomness: &BigInt,
+    ) -> anyhow::Result<Vec<OpeningProof>> {
+        let cols = values.first().map_or(0, |row| row.len());
+        if values.iter().any(|row| row.len() != cols) {
+            return Err(anyhow!("Values rows have different lengths."));
+        }
+        Ok(values
+            .par_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .zip(generators(values.len(), cols))
+            .map(|(w, h)| OpeningProof::prove(w, randomness, &h))
+            .collect())
+    }
+
+    /// Opens the aggregate of `n_client` datasets, whose cells are each at
+    /// most `n_client` times their column maximum.
+    pub fn open_table(
+        commitments: &CommitmentMatrix,
+        randomness: &BigInt,
+        schema: &Schema,
+        n_client: usize,
+    ) -> anyhow::Result<Table> {
+        commitments.check_shape(commitments.rows, schema.len())?;
+        let bounds: Vec<BigInt> = schema.columns.iter().map(|el| &el.max * n_client).collect();
+        let values = Self::open_commitments(commitments, randomness, &bounds)?;
+        schema.table(&values)
+    }
 }
 
-fn transpose_dataset<T>(dataset: &[Vec<T>]) -> Vec<Vec<T>>
-where
-    T: Send + Sync + Clone,
-{
-    (0..dataset[0].len())
-        .map(|col| dataset.par_iter().map(|row| row[col].clone()).collect())
-        .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::commitment::generator;
+    use crate::system::client::Client;
+    use crate::system::masking::MaskingKey;
+    use crate::util::dataset::Dataset;
+    use ark_bls12_381::G1Projective;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_aggregate_commitments_shape() {
+        let matrix = |rows: usize, cols: usize| {
+            let data = generators(rows, cols)
+                .iter()
+                .enumerate()
+                .map(|(i, h)| Commitment::commit(&BigInt::from(i), &BigInt::from(3), h))
+                .collect();
+            CommitmentMatrix::new(rows, cols, data).unwrap()
+        };
+
+        let aggregated = Aggregator::aggregate_commitments(&[matrix(3, 2), matrix(3, 2)]).unwrap();
+        assert_eq!(aggregated.shape(), (3, 2));
+        assert!(aggregated
+            .get(2, 1)
+            .unwrap()
+            .open(&BigInt::from(10), &BigInt::from(6), &generator(2, 1))
+            .is_ok());
+
+        assert!(Aggregator::aggregate_commitments(&[matrix(3, 2), matrix(2, 3)]).is_err());
+        assert!(Aggregator::aggregate_commitments(&[]).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_wrong_randomness() {
+        let mkhs = Mkhs::setup(2);
+        let key_pair = mkhs.generate_keys(1);
+        let dataset = Dataset::new(&[vec![BigInt::from(1), BigInt::from(2)]]);
+        let client = Client::new(1, key_pair, dataset, BigInt::from(5));
+
+        let randomness = client.randomness() + 1;
+        let result = Aggregator::aggregate(
+            &mkhs,
+            &client.dataset.schema,
+            &[client.compute_commitments()],
+            &[client
+                .compute_signature(&mkhs, &client.dataset.fr())
+                .unwrap()],
+            &[client.commit_randomness()],
+            &randomness,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_verify_range_proofs() {
+        let mkhs = Mkhs::setup(2);
+        let schema = Schema::build(2, 1);
+        let data = vec![vec![BigInt::from(3), BigInt::from(10)]];
+        let dataset = Dataset::with_schema(&schema, &data).unwrap();
+        let mut client = Client::new(1, mkhs.generate_keys(1), dataset, BigInt::from(5));
+
+        let commitments = client.compute_commitments();
+        let randomness_commitment = client.commit_randomness();
+        let proofs = client.compute_range_proofs().unwrap();
+        assert!(Aggregator::verify_range_proofs(
+            &schema,
+            &commitments,
+            &randomness_commitment,
+            &proofs
+        )
+        .is_ok());
+        assert!(Aggregator::verify_range_proofs(
+            &schema,
+            &commitments,
+            &randomness_commitment,
+            &proofs[..1]
+        )
+        .is_err());
+
+        // Out-of-range values cannot be proven, and old proofs do not carry over.
+        client.dataset.dataset[0][1] = BigInt::from(11);
+        assert!(client.compute_range_proofs().is_err());
+        assert!(Aggregator::verify_range_proofs(
+            &schema,
+            &client.compute_commitments(),
+            &randomness_commitment,
+            &proofs
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_verify_consistency_proof() {
+        let mkhs = Mkhs::setup(2);
+        let schema = Schema::build(2, 1);
+        let data = vec![vec![BigInt::from(3), BigInt::from(10)]];
+        let dataset = Dataset::with_schema(&schema, &data).unwrap();
+        let mut client = Client::new(1, mkhs.generate_keys(1), dataset, BigInt::from(5));
+
+        let commitments = client.compute_commitments();
+        let randomness_commitment = client.commit_randomness();
+        let (signature, proof) = client.compute_signature_with_proof(&mkhs).unwrap();
+        assert!(Aggregator::verify_consistency_proof(
+            &mkhs,
+            client.id,
+            &schema,
+            &commitments,
+            &randomness_commitment,
+            &signature,
+            &proof
+        )
+        .is_ok());
+
+        assert!(Aggregator::verify_consistency_proof(
+            &mkhs,
+            2,
+            &schema,
+            &commitments,
+            &randomness_commitment,
+            &signature,
+            &proof
+        )
+        .is_err());
+
+        // The signature is bound to the schema of the dataset.
+        assert!(Aggregator::verify_consistency_proof(
+            &mkhs,
+            client.id,
+            &Schema::build(2, 2),
+            &commitments,
+            &randomness_commitment,
+            &signature,
+            &proof
+        )
+        .is_err());
+
+        // Signing a different dataset than the committed one is caught.
+        client.dataset.dataset[0][1] = BigInt::from(9);
+        let (signature, proof) = client.compute_signature_with_proof(&mkhs).unwrap();
+        assert!(Aggregator::verify_consistency_proof(
+            &mkhs,
+            client.id,
+            &schema,
+            &commitments,
+            &randomness_commitment,
+            &signature,
+            &proof
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_tree_aggregation() {
+        let mkhs = Mkhs::setup(2);
+        let schema = Schema::build(2, 2);
+        let clients: Vec<Client> = (1..=4u64)
+            .map(|id| {
+                let data = vec![vec![BigInt::from(id), BigInt::from(10 * id)]];
+                let dataset = Dataset::with_schema(&schema, &data).unwrap();
+                Client::new(id, mkhs.generate_keys(id), dataset, BigInt::from(id * 7))
+            })
+            .collect();
+        let keys: Vec<MaskingKey> = clients.iter().map(|_| MaskingKey::generate()).collect();
+        let peers: HashMap<u64, G1Projective> = clients
+            .iter()
+            .zip(&keys)
+            .map(|(client, key)| (client.id, key.public))
+            .collect();
+        let leaves: Vec<PartialAggregate> = clients
+            .iter()
+            .zip(&keys)
+            .map(|(client, key)| {
+                PartialAggregate::leaf(
+                    client.id,
+                    client.compute_commitments(),
+                    client.commit_randomness(),
+                    client
+                        .compute_signature(&mkhs, &client.dataset.fr())
+                        .unwrap(),
+                    client.masked_randomness(key, &peers),
+                )
+            })
+            .collect();
+
+        let left = Aggregator::combine(&mkhs, &leaves[..2]).unwrap();
+        let right = Aggregator::combine(&mkhs, &leaves[2..]).unwrap();
+        // Subtrees alone cannot be opened: their masks do not cancel.
+        assert!(Aggregator::finalize(&schema, &left).is_err());
+        assert!(Aggregator::combine(&mkhs, &[left.clone(), leaves[1].clone()]).is_err());
+
+        let root = Aggregator::combine(&mkhs, &[left, right]).unwrap();
+        let result = Aggregator::finalize(&schema, &root).unwrap();
+        assert_eq!(
+            result.table.rows,
+            vec![vec![BigInt::from(10), BigInt::from(100)]]
+        );
+        let pks = clients
+            .iter()
+            .map(|client| (client.id, client.key_pair.pk.clone()))
+            .collect();
+        assert!(Client::verify_result(&mkhs, &pks, &result, &root.masked_randomness).is_ok());
+        assert!(Client::audit_result(&mkhs, &pks, &result).is_ok());
+    }
+
+    #[test]
+    fn test_recover_dropped_randomness() {
+        let ids = [1u64, 2, 3];
+        let seeds: Vec<BigInt> = ids.iter().map(|id| BigInt::from(id * 100)).collect();
+        let randomness: Vec<BigInt> = seeds
+            .iter()
+            .map(|seed| derive_randomness(seed, 0))
+            .collect();
+        let keys: Vec<MaskingKey> = ids.iter().map(|_| MaskingKey::generate()).collect();
+        let peers: HashMap<u64, G1Projective> = ids
+            .iter()
+            .zip(keys.iter())
+            .map(|(id, key)| (*id, key.public))
+            .collect();
+
+        // Every client shares its seed before the third one drops out.
+        let shares: Vec<Vec<Share>> = seeds
+            .iter()
+            .map(|seed| shamir::share(seed, &ids, 2).unwrap())
+            .collect();
+        let dropped = HashMap::from([(3, keys[2].public)]);
+
+        let masked: Vec<BigInt> = (0..2)
+            .flat_map(|i| {
+                [
+                    keys[i].mask(ids[i], 0, &randomness[i], &peers),
+                    keys[i].dropout_correction(ids[i], 0, &dropped),
+                ]
+            })
+            .collect();
+        let partial_randomness = Aggregator::unmask_randomness(&masked);
+
+        let missing_shares = vec![shares[2][..2].to_vec()];
+        let recovered =
+            Aggregator::recover_randomness(&partial_randomness, &missing_shares, 2, 0).unwrap();
+        assert_eq!(recovered, masking::unmask(&randomness));
+
+        let missing_shares = vec![shares[2][..1].to_vec()];
+        assert!(
+            Aggregator::recover_randomness(&partial_randomness, &missing_shares, 2, 0).is_err()
+        );
+    }
 }
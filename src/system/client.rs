@@ -1,16 +1,28 @@
-use crate::primitives::commitment::Commitment;
+use crate::primitives::commitment::{derive_randomness, generators, Commitment, CommitmentMatrix};
+use crate::primitives::consistency_proof::ConsistencyProof;
+use crate::primitives::ec::Point;
 use crate::primitives::mkhs::{KeyPair, Mkhs, Signature, PK};
+use crate::primitives::norm_proof::{NormBound, NormProof};
+use crate::primitives::range_proof::RangeProof;
+use crate::primitives::shamir::{self, Share};
+use crate::system::aggregator::AggregationResult;
+use crate::system::masking::MaskingKey;
+use crate::system::protocol::Upload;
+use crate::system::session::Submission;
 use crate::util::dataset::Dataset;
-use ark_bls12_381::Fr;
+use crate::util::schema::Schema;
+use anyhow::anyhow;
+use ark_bls12_381::{Fr, G1Projective};
 use num_bigint::BigInt;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 pub struct Client {
     pub id: u64,
     pub key_pair: KeyPair,
     pub dataset: Dataset,
     pub secret: BigInt,
+    pub round: u64,
 }
 
 impl Client {
@@ -20,125 +32,462 @@ impl Client {
             key_pair,
             dataset,
             secret,
+            round: 0,
         }
     }
 
-    pub fn compute_commitments(&self) -> Vec<Commitment> {
+    /// The randomness of every commitment of the current round.
+    pub fn randomness(&self) -> BigInt {
+        derive_randomness(&self.secret, self.round)
+    }
+
+    /// The generators of every cell of the dataset, in row-major order.
+    pub fn generators(&self) -> Vec<Point> {
+        generators(self.dataset.dataset.len(), self.dataset.schema.len())
+    }
+
+    pub fn compute_commitments(&self) -> CommitmentMatrix {
+        let randomness = self.randomness();
+        let data = self
+            .cells()
+            .par_iter()
+            .zip(self.generators())
+            .map(|(el, h)| Commitment::commit(el, &randomness, &h))
+            .collect();
+        CommitmentMatrix {
+            rows: self.dataset.dataset.len(),
+            cols: self.dataset.schema.len(),
+            data,
+        }
+    }
+
+    fn cells(&self) -> Vec<&BigInt> {
+        self.dataset.dataset.iter().flatten().collect()
+    }
+
+    pub fn commit_randomness(&self) -> Commitment {
+        Commitment::commit_randomness(&self.randomness())
+    }
+
+    pub fn compute_range_proofs(&self) -> anyhow::Result<Vec<RangeProof>> {
+        let columns = &self.dataset.schema.columns;
+        let randomness = self.randomness();
+        self.cells()
+            .par_iter()
+            .zip(self.generators())
+            .enumerate()
+            .map(|(i, (el, h))| {
+                let column = &columns[i % columns.len()];
+                RangeProof::prove(el, &randomness, &h, &column.min, &column.max)
+            })
+            .collect()
+    }
+
+    pub fn compute_norm_proofs(&self, bound: &NormBound) -> anyhow::Result<Vec<NormProof>> {
+        let randomness = self.randomness();
+        let generators = self.generators();
         self.dataset
             .dataset
             .par_iter()
-            .flatten()
-            .map(|el| Commitment::commit(el, &self.secret))
+            .zip(generators.par_chunks(self.dataset.schema.len().max(1)))
+            .map(|(row, hs)| NormProof::prove(bound, row, &randomness, hs))
             .collect()
     }
 
     pub fn verify_commitment(
-        commitments: &[Commitment],
-        aggregated_data: &[BigInt],
-        random: &BigInt,
+        commitments: &CommitmentMatrix,
+        aggregated_data: &[Vec<BigInt>],
+        randomness: &BigInt,
     ) -> anyhow::Result<()> {
+        let cols = aggregated_data.first().map_or(0, |row| row.len());
+        if aggregated_data.iter().any(|row| row.len() != cols) {
+            return Err(anyhow!("Aggregated data rows have different lengths."));
+        }
+        commitments.check_shape(aggregated_data.len(), cols)?;
         commitments
+            .data
             .par_iter()
+            .zip(commitments.generators())
             .enumerate()
-            .try_for_each(|(i, el)| el.open(&aggregated_data[i], random))
+            .try_for_each(|(i, (el, h))| {
+                el.open(&aggregated_data[i / cols][i % cols], randomness, &h)
+            })
     }
 
-    pub fn compute_signature(&self, mkhs: &Mkhs, messages: &[Vec<Fr>]) -> Vec<Signature> {
-        messages
-            .par_iter()
-            .map(|row| mkhs.sign(&self.key_pair.sk, row))
-            .collect()
+    pub fn masked_randomness(
+        &self,
+        masking_key: &MaskingKey,
+        peers: &HashMap<u64, G1Projective>,
+    ) -> BigInt {
+        masking_key.mask(self.id, self.round, &self.randomness(), peers)
     }
 
+    pub fn share_secret(&self, holders: &[u64], threshold: usize) -> anyhow::Result<Vec<Share>> {
+        shamir::share(&self.secret, holders, threshold)
+    }
+
+    /// Signs `messages` as a whole dataset with a single signature bound to
+    /// the round and the dataset's schema.
+    pub fn compute_signature(
+        &self,
+        mkhs: &Mkhs,
+        messages: &[Vec<Fr>],
+    ) -> anyhow::Result<Signature> {
+        let context = self.dataset.schema.digest();
+        mkhs.sign_dataset(&self.key_pair.sk, self.round, &context, messages)
+    }
+
+    pub fn upload(
+        &self,
+        mkhs: &Mkhs,
+        masking_key: &MaskingKey,
+        peers: &HashMap<u64, G1Projective>,
+        norm_bound: Option<&NormBound>,
+    ) -> anyhow::Result<Upload> {
+        let norm_proofs = match norm_bound {
+            Some(bound) => self.compute_norm_proofs(bound)?,
+            None => vec![],
+        };
+        let (signature, consistency_proof) = self.compute_signature_with_proof(mkhs)?;
+        Ok(Upload {
+            commitments: self.compute_commitments(),
+            randomness_commitment: self.commit_randomness(),
+            range_proofs: self.compute_range_proofs()?,
+            norm_proofs,
+            stats_proofs: vec![],
+            signature,
+            consistency_proof,
+            masked_randomness: self.masked_randomness(masking_key, peers),
+        })
+    }
+
+    pub fn submission(
+        &self,
+        mkhs: &Mkhs,
+        masking_key: &MaskingKey,
+        peers: &HashMap<u64, G1Projective>,
+        norm_bound: Option<&NormBound>,
+    ) -> anyhow::Result<Submission> {
+        let upload = self.upload(mkhs, masking_key, peers, norm_bound)?;
+        Ok(Submission::sign(&self.key_pair.sk, self.round, upload))
+    }
+
+    /// Signs the dataset and proves the signature is over the committed
+    /// dataset.
+    pub fn compute_signature_with_proof(
+        &self,
+        mkhs: &Mkhs,
+    ) -> anyhow::Result<(Signature, ConsistencyProof)> {
+        let randomness = self.randomness();
+        let context = self.dataset.schema.digest();
+        let (signature, s) = mkhs.sign_dataset_with_randomness(
+            &self.key_pair.sk,
+            self.round,
+            &context,
+            &self.dataset.fr(),
+        )?;
+        let proof = ConsistencyProof::prove(
+            mkhs,
+            self.id,
+            &context,
+            &s,
+            &self.dataset.dataset,
+            &randomness,
+        )?;
+        Ok((signature, proof))
+    }
+
+    /// Verifies the aggregated dataset signature, expecting every client in
+    /// `pks` and no other to have contributed to it under `schema`.
     pub fn verify_signature(
         mkhs: &Mkhs,
         pks: &HashMap<u64, PK>,
+        schema: &Schema,
         aggregated_data: &[Vec<Fr>],
-        aggregated_signatures: &[Signature],
+        aggregated_signature: &Signature,
     ) -> anyhow::Result<()> {
-        aggregated_signatures
-            .par_iter()
-            .enumerate()
-            .try_for_each(|(i, signature)| mkhs.verify(pks, &aggregated_data[i], signature))
+        let expected: BTreeSet<u64> = pks.keys().copied().collect();
+        mkhs.verify_dataset_participants(
+            pks,
+            &expected,
+            &schema.digest(),
+            aggregated_data,
+            aggregated_signature,
+        )
+    }
+
+    pub fn verify_result(
+        mkhs: &Mkhs,
+        pks: &HashMap<u64, PK>,
+        result: &AggregationResult,
+        randomness: &BigInt,
+    ) -> anyhow::Result<()> {
+        let (commitment_check, signature_check) = rayon::join(
+            || {
+                result.randomness_commitment.open_randomness(randomness)?;
+                Self::verify_commitment(&result.commitments, &result.table.rows, randomness)
+            },
+            || {
+                Self::verify_signature(
+                    mkhs,
+                    pks,
+                    &result.table.schema,
+                    &result.fr(),
+                    &result.signature,
+                )
+            },
+        );
+
+        match (commitment_check, signature_check) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(e), Ok(())) => Err(anyhow!("Commitment check failed: {}", e)),
+            (Ok(()), Err(e)) => Err(anyhow!("Signature check failed: {}", e)),
+            (Err(e1), Err(e2)) => Err(anyhow!(
+                "Commitment check failed: {} Signature check failed: {}",
+                e1,
+                e2
+            )),
+        }
+    }
+
+    /// Verifies a result without the aggregated randomness, relying on the
+    /// aggregator's opening proofs instead. Lets third parties audit a round.
+    pub fn audit_result(
+        mkhs: &Mkhs,
+        pks: &HashMap<u64, PK>,
+        result: &AggregationResult,
+    ) -> anyhow::Result<()> {
+        let (opening_check, signature_check) = rayon::join(
+            || result.verify_openings(),
+            || {
+                Self::verify_signature(
+                    mkhs,
+                    pks,
+                    &result.table.schema,
+                    &result.fr(),
+                    &result.signature,
+                )
+            },
+        );
+        opening_check.map_err(|e| anyhow!("Opening check failed: {}", e))?;
+        signature_check.map_err(|e| anyhow!("Signature check failed: {}", e))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::system::aggregator::Aggregator;
+    use crate::system::masking::unmask;
 
     #[test]
     fn test_single_client() {
-        let mkhs = Mkhs::setup(1, 2);
+        let mkhs = Mkhs::setup(2);
         let client = init_client(&mkhs, 1);
 
         let commitments = client.compute_commitments();
-        let flatten_dataset = client
-            .dataset
-            .dataset
-            .iter()
-            .flatten()
-            .map(|e| e.clone())
-            .collect::<Vec<BigInt>>();
-        let check = Client::verify_commitment(&commitments, &flatten_dataset, &client.secret);
+        assert_eq!(commitments.shape(), (2, 2));
+        let randomness = client.randomness();
+        let check = Client::verify_commitment(&commitments, &client.dataset.dataset, &randomness);
         assert!(check.is_ok());
 
-        let signature = client.compute_signature(&mkhs, &client.dataset.fr());
+        let check =
+            Client::verify_commitment(&commitments, &client.dataset.dataset[..1], &randomness);
+        assert!(check.is_err());
+
+        let signature = client
+            .compute_signature(&mkhs, &client.dataset.fr())
+            .unwrap();
         let pk = HashMap::from([(client.id, client.key_pair.pk)]);
-        let check = Client::verify_signature(&mkhs, &pk, &client.dataset.fr(), &signature);
+        let check = Client::verify_signature(
+            &mkhs,
+            &pk,
+            &client.dataset.schema,
+            &client.dataset.fr(),
+            &signature,
+        );
         assert!(check.is_ok());
     }
 
     #[test]
     fn test_multi_client() {
-        let mkhs = Mkhs::setup(2, 2);
+        let mkhs = Mkhs::setup(2);
 
         let c1 = init_client(&mkhs, 1);
         let c2 = init_client(&mkhs, 2);
 
         let commitments1 = c1.compute_commitments();
-        let signature1 = c1.compute_signature(&mkhs, &c1.dataset.fr());
+        let signature1 = c1.compute_signature(&mkhs, &c1.dataset.fr()).unwrap();
 
         let commitments2 = c2.compute_commitments();
-        let signature2 = c2.compute_signature(&mkhs, &c2.dataset.fr());
+        let signature2 = c2.compute_signature(&mkhs, &c2.dataset.fr()).unwrap();
 
         let agg_commitment: Vec<Commitment> = commitments1
+            .data
             .par_iter()
-            .zip(commitments2)
+            .zip(commitments2.data)
             .map(|(c1, c2)| c1 + &c2)
             .collect();
-        let agg_signature: Vec<Signature> = signature1
-            .par_iter()
-            .zip(signature2)
-            .map(|(s1, s2)| mkhs.eval(&[s1.clone(), s2]))
-            .collect();
+        let agg_commitment = CommitmentMatrix::new(2, 2, agg_commitment).unwrap();
+        let agg_signature = mkhs.eval(&[signature1, signature2]);
 
         let agg_dataset = Dataset::new(&[
             vec![BigInt::from(2), BigInt::from(4)],
             vec![BigInt::from(6), BigInt::from(8)],
         ]);
-        let agg_dataset_flatten: Vec<BigInt> = agg_dataset
-            .dataset
-            .iter()
-            .flatten()
-            .map(|el| el.clone())
-            .collect();
 
         let check = Client::verify_commitment(
             &agg_commitment,
-            &agg_dataset_flatten,
-            &(c1.secret + c2.secret),
+            &agg_dataset.dataset,
+            &unmask(&[c1.randomness(), c2.randomness()]),
         );
         assert!(check.is_ok());
 
         let pk = HashMap::from([(c1.id, c1.key_pair.pk), (c2.id, c2.key_pair.pk)]);
-        let check = Client::verify_signature(&mkhs, &pk, &agg_dataset.fr(), &agg_signature);
+        let check = Client::verify_signature(
+            &mkhs,
+            &pk,
+            &agg_dataset.schema,
+            &agg_dataset.fr(),
+            &agg_signature,
+        );
         assert!(check.is_ok());
     }
 
+    #[test]
+    fn test_verify_result() {
+        let mkhs = Mkhs::setup(2);
+
+        let c1 = init_client(&mkhs, 1);
+        let c2 = init_client(&mkhs, 2);
+
+        let commitments = Aggregator::aggregate_commitments(&[
+            c1.compute_commitments(),
+            c2.compute_commitments(),
+        ])
+        .unwrap();
+        let signature = Aggregator::aggregate_signatures(
+            &mkhs,
+            &[
+                c1.compute_signature(&mkhs, &c1.dataset.fr()).unwrap(),
+                c2.compute_signature(&mkhs, &c2.dataset.fr()).unwrap(),
+            ],
+        )
+        .unwrap();
+        let table = c1
+            .dataset
+            .schema
+            .table(&[
+                vec![BigInt::from(2), BigInt::from(4)],
+                vec![BigInt::from(6), BigInt::from(8)],
+            ])
+            .unwrap();
+
+        let randomness_commitment = &c1.commit_randomness() + &c2.commit_randomness();
+
+        let randomness = unmask(&[c1.randomness(), c2.randomness()]);
+        let opening_proofs = Aggregator::prove_openings(&table.rows, &randomness).unwrap();
+        let mut result = AggregationResult {
+            commitments,
+            randomness_commitment,
+            signature,
+            table,
+            opening_proofs,
+        };
+        let pk = HashMap::from([(c1.id, c1.key_pair.pk), (c2.id, c2.key_pair.pk)]);
+        assert!(Client::verify_result(&mkhs, &pk, &result, &randomness).is_ok());
+
+        assert!(Client::audit_result(&mkhs, &pk, &result).is_ok());
+
+        let wrong_randomness = &randomness + 1;
+        assert!(Client::verify_result(&mkhs, &pk, &result, &wrong_randomness).is_err());
+
+        // The data was signed under its schema, so it cannot be opened under
+        // another one.
+        let schema = std::mem::replace(&mut result.table.schema, Schema::build(2, 2));
+        assert!(Client::audit_result(&mkhs, &pk, &result)
+            .unwrap_err()
+            .to_string()
+            .contains("Signature check failed"));
+        result.table.schema = schema;
+
+        result.table.rows[1][0] = BigInt::from(7);
+        let check = Client::verify_result(&mkhs, &pk, &result, &randomness);
+        assert!(check
+            .unwrap_err()
+            .to_string()
+            .contains("Commitment check failed"));
+        assert!(Client::audit_result(&mkhs, &pk, &result)
+            .unwrap_err()
+            .to_string()
+            .contains("Opening check failed"));
+    }
+
+    #[test]
+    fn test_dataset_signature() {
+        let mkhs = Mkhs::setup(2);
+        let c1 = init_client(&mkhs, 1);
+        let c2 = init_client(&mkhs, 2);
+        let pks = HashMap::from([
+            (c1.id, c1.key_pair.pk.clone()),
+            (c2.id, c2.key_pair.pk.clone()),
+        ]);
+
+        let signature = mkhs.eval(&[
+            c1.compute_signature(&mkhs, &c1.dataset.fr()).unwrap(),
+            c2.compute_signature(&mkhs, &c2.dataset.fr()).unwrap(),
+        ]);
+        let mut aggregated = Dataset::new(&[
+            vec![BigInt::from(2), BigInt::from(4)],
+            vec![BigInt::from(6), BigInt::from(8)],
+        ]);
+        assert!(Client::verify_signature(
+            &mkhs,
+            &pks,
+            &aggregated.schema,
+            &aggregated.fr(),
+            &signature
+        )
+        .is_ok());
+
+        // Swapping rows changes the signed dataset.
+        aggregated.dataset.swap(0, 1);
+        assert!(Client::verify_signature(
+            &mkhs,
+            &pks,
+            &aggregated.schema,
+            &aggregated.fr(),
+            &signature
+        )
+        .is_err());
+        let c1_only = HashMap::from([(c1.id, c1.key_pair.pk.clone())]);
+        assert_eq!(
+            Client::verify_signature(
+                &mkhs,
+                &c1_only,
+                &c1.dataset.schema,
+                &c1.dataset.fr(),
+                &signature
+            )
+            .unwrap_err()
+            .to_string(),
+            "Unexpected participants [2]."
+        );
+    }
+
+    #[test]
+    fn test_per_cell_generators() {
+        let mkhs = Mkhs::setup(2);
+        let mut client = init_client(&mkhs, 1);
+        client.dataset = Dataset::new(&[vec![BigInt::from(5), BigInt::from(5)]]);
+
+        let commitments = client.compute_commitments();
+        assert_ne!(commitments.get(0, 0), commitments.get(0, 1));
+    }
+
     fn init_client(mkhs: &Mkhs, id: u64) -> Client {
         let client_id: u64 = id;
-        let secret = BigInt::from(11);
+        let secret = BigInt::from(10 + id);
 
         let dataset: Vec<Vec<BigInt>> = vec![
             vec![BigInt::from(1), BigInt::from(2)],
@@ -0,0 +1,192 @@
+use crate::primitives::mkhs::PK;
+use crate::system::client::Client;
+use crate::system::masking::MaskingKey;
+use crate::system::protocol::{read_message, write_message, Message};
+use crate::system::session::Submission;
+use crate::util::dataset::Dataset;
+use crate::util::schema::Table;
+use anyhow::anyhow;
+use num_bigint::BigInt;
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+pub struct Connection {
+    addr: String,
+    poll_interval: Duration,
+}
+
+impl Connection {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            poll_interval: Duration::from_millis(200),
+        }
+    }
+
+    pub fn request(&self, message: &Message) -> anyhow::Result<Message> {
+        let mut stream = TcpStream::connect(&self.addr)?;
+        write_message(&mut stream, message)?;
+        match read_message(&mut stream)? {
+            Message::Error(e) => Err(anyhow!("Server error: {}", e)),
+            response => Ok(response),
+        }
+    }
+
+    pub fn poll(&self, message: &Message) -> anyhow::Result<Message> {
+        loop {
+            match self.request(message)? {
+                Message::NotReady => thread::sleep(self.poll_interval),
+                response => return Ok(response),
+            }
+        }
+    }
+}
+
+pub fn run_client(addr: &str, id: u64, n_row: usize, secret: BigInt) -> anyhow::Result<Table> {
+    let connection = Connection::new(addr);
+
+    let Message::Params {
+        mkhs,
+        schema,
+        norm_bound,
+        stats,
+    } = connection.request(&Message::GetParams)?
+    else {
+        return Err(anyhow!("Unexpected response to GetParams."));
+    };
+    let key_pair = mkhs.generate_keys(id);
+    let client = match &stats {
+        Some(encoder) => {
+            let dataset = Dataset::sample(encoder.source(), n_row);
+            encoder.client(&Client::new(id, key_pair, dataset, secret))?
+        }
+        None => Client::new(id, key_pair, Dataset::sample(&schema, n_row), secret),
+    };
+    let masking_key = MaskingKey::generate();
+
+    connection.request(&Message::Register {
+        id,
+        pk: client.key_pair.pk.clone(),
+        masking_key: masking_key.public,
+    })?;
+    let Message::Peers(peers) = connection.poll(&Message::GetPeers)? else {
+        return Err(anyhow!("Unexpected response to GetPeers."));
+    };
+
+    let upload = match &stats {
+        Some(encoder) => {
+            encoder.upload(&client, &mkhs, &masking_key, &peers, norm_bound.as_ref())?
+        }
+        None => client.upload(&mkhs, &masking_key, &peers, norm_bound.as_ref())?,
+    };
+    let submission = Submission::sign(&client.key_pair.sk, client.round, upload);
+    connection.request(&Message::Upload(submission))?;
+
+    let Message::Result {
+        result,
+        randomness,
+        pks,
+    } = connection.poll(&Message::GetResult)?
+    else {
+        return Err(anyhow!("Unexpected response to GetResult."));
+    };
+    check_own_key(&client, &pks)?;
+    Client::verify_result(&mkhs, &pks, &result, &randomness)?;
+
+    Ok(result.table)
+}
+
+/// Checks the keys a result is verified against hold `client`'s own key, so
+/// the server cannot substitute it.
+fn check_own_key(client: &Client, pks: &HashMap<u64, PK>) -> anyhow::Result<()> {
+    match pks.get(&client.id) {
+        None => Err(anyhow!("Client {} missing from the result.", client.id)),
+        Some(pk) if pk != &client.key_pair.pk => Err(anyhow!(
+            "Result holds another key for client {}.",
+            client.id
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::mkhs::Mkhs;
+    use crate::primitives::norm_proof::{Norm, NormBound};
+    use crate::system::server::Server;
+    use crate::system::stats::StatsEncoder;
+    use crate::util::schema::Schema;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_loopback_round() {
+        let n_client = 2;
+        let schema = Schema::build(1, 2);
+        let bound = NormBound::new(Norm::LInf, BigInt::from(50), BigInt::from(50));
+        let server = Server::new(Mkhs::setup(schema.len()), schema, n_client)
+            .with_norm_bound(bound)
+            .unwrap();
+        let server = Arc::new(server);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || server.serve(listener));
+
+        let handles: Vec<_> = (1..=n_client as u64)
+            .map(|id| {
+                let addr = addr.clone();
+                thread::spawn(move || run_client(&addr, id, 1, BigInt::from(id * 1000)))
+            })
+            .collect();
+        let tables: Vec<Table> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap().unwrap())
+            .collect();
+
+        assert_eq!(tables[0], tables[1]);
+        assert_eq!(tables[0].rows.len(), 1);
+    }
+
+    #[test]
+    fn test_loopback_stats_round() {
+        let n_client = 2;
+        let encoder = StatsEncoder::new(&Schema::build(1, 1), 1, n_client, vec![]).unwrap();
+        let schema = encoder.schema().clone();
+        let server = Server::new(Mkhs::setup(schema.len()), schema, n_client)
+            .with_stats(encoder.clone())
+            .unwrap();
+        let server = Arc::new(server);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || server.serve(listener));
+
+        let handles: Vec<_> = (1..=n_client as u64)
+            .map(|id| {
+                let addr = addr.clone();
+                thread::spawn(move || run_client(&addr, id, 1, BigInt::from(id * 1000)))
+            })
+            .collect();
+        for handle in handles {
+            let table = handle.join().unwrap().unwrap();
+            assert_eq!(encoder.decode(&table).unwrap().count, BigInt::from(2));
+        }
+    }
+
+    #[test]
+    fn test_substituted_key() {
+        let mkhs = Mkhs::setup(1);
+        let dataset = Dataset::new(&[vec![BigInt::from(1)]]);
+        let client = Client::new(1, mkhs.generate_keys(1), dataset, BigInt::from(7));
+
+        let pks = HashMap::from([(1, client.key_pair.pk.clone())]);
+        assert!(check_own_key(&client, &pks).is_ok());
+        let substituted = HashMap::from([(1, mkhs.generate_keys(1).pk)]);
+        assert!(check_own_key(&client, &substituted).is_err());
+        assert!(check_own_key(&client, &HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::primitives::ec::DEFAULTEC;
+use ark_bls12_381::{Fr, G1Projective};
+use ark_ec::Group;
+use ark_serialize::CanonicalSerializeHashExt;
+use ark_std::UniformRand;
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, Zero};
+use rand::thread_rng;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MaskingKey {
+    pub(crate) secret: Fr,
+    pub public: G1Projective,
+}
+
+impl MaskingKey {
+    pub fn generate() -> Self {
+        let secret = Fr::rand(&mut thread_rng());
+        Self {
+            secret,
+            public: G1Projective::generator() * secret,
+        }
+    }
+
+    pub fn shared(&self, peer: &G1Projective) -> Vec<u8> {
+        (*peer * self.secret).hash::<Sha256>().to_vec()
+    }
+
+    /// Masks `value` with one pairwise mask per peer, derived from the
+    /// shared key and `round`, so that masks differ between rounds even with
+    /// the same keys.
+    pub fn mask(
+        &self,
+        id: u64,
+        round: u64,
+        value: &BigInt,
+        peers: &HashMap<u64, G1Projective>,
+    ) -> BigInt {
+        let masks: BigInt = peers
+            .par_iter()
+            .filter(|(peer_id, _)| **peer_id != id)
+            .map(|(peer_id, peer)| {
+                let mask = pairwise_mask(&self.shared(peer), round);
+                if id < *peer_id {
+                    mask
+                } else {
+                    -mask
+                }
+            })
+            .sum();
+        (value + masks).modpow(&BigInt::one(), &DEFAULTEC.n)
+    }
+
+    pub fn dropout_correction(
+        &self,
+        id: u64,
+        round: u64,
+        dropped: &HashMap<u64, G1Projective>,
+    ) -> BigInt {
+        (-self.mask(id, round, &BigInt::zero(), dropped)).modpow(&BigInt::one(), &DEFAULTEC.n)
+    }
+}
+
+pub fn pairwise_mask(shared: &[u8], round: u64) -> BigInt {
+    let digest = Sha256::new()
+        .chain_update(shared)
+        .chain_update(round.to_le_bytes())
+        .finalize();
+    BigInt::from_bytes_le(Sign::Plus, &digest).modpow(&BigInt::one(), &DEFAULTEC.n)
+}
+
+pub fn unmask(masked: &[BigInt]) -> BigInt {
+    masked
+        .iter()
+        .sum::<BigInt>()
+        .modpow(&BigInt::one(), &DEFAULTEC.n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_keys(n: usize) -> (Vec<MaskingKey>, HashMap<u64, G1Projective>) {
+        let keys: Vec<MaskingKey> = (0..n).map(|_| MaskingKey::generate()).collect();
+        let peers = keys
+            .iter()
+            .enumerate()
+            .map(|(id, key)| (id as u64, key.public))
+            .collect();
+        (keys, peers)
+    }
+
+    #[test]
+    fn test_masks_cancel() {
+        let values: Vec<BigInt> = (1..=3).map(|i| BigInt::from(i * 10)).collect();
+        let (keys, peers) = init_keys(3);
+
+        let masked: Vec<BigInt> = keys
+            .iter()
+            .enumerate()
+            .map(|(id, key)| key.mask(id as u64, 1, &values[id], &peers))
+            .collect();
+
+        assert!(masked.iter().zip(values.iter()).all(|(m, v)| m != v));
+        assert_eq!(unmask(&masked), BigInt::from(60));
+
+        // The same keys give different masks in another round.
+        assert_ne!(keys[0].mask(0, 2, &values[0], &peers), masked[0]);
+    }
+
+    #[test]
+    fn test_dropout_correction() {
+        let (keys, peers) = init_keys(3);
+        let dropped = HashMap::from([(2, keys[2].public)]);
+        let value = BigInt::from(5);
+
+        let masked: Vec<BigInt> = (0..2)
+            .map(|id| keys[id].mask(id as u64, 1, &value, &peers))
+            .chain((0..2).map(|id| keys[id].dropout_correction(id as u64, 1, &dropped)))
+            .collect();
+
+        assert_eq!(unmask(&masked), BigInt::from(10));
+    }
+}
@@ -1,2 +1,9 @@
 pub mod aggregator;
 pub mod client;
+pub mod daemon;
+pub mod masking;
+pub mod protocol;
+pub mod registry;
+pub mod server;
+pub mod session;
+pub mod stats;
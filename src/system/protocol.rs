@@ -0,0 +1,237 @@
+use crate::primitives::commitment::{Commitment, CommitmentMatrix};
+use crate::primitives::consistency_proof::ConsistencyProof;
+use crate::primitives::mkhs::{Mkhs, Signature, PK};
+use crate::primitives::norm_proof::{NormBound, NormProof};
+use crate::primitives::range_proof::RangeProof;
+use crate::primitives::stats_proof::StatsProof;
+use crate::system::aggregator::AggregationResult;
+use crate::system::session::Submission;
+use crate::system::stats::StatsEncoder;
+use crate::util::codec::{Decode, Encode};
+use crate::util::schema::Schema;
+use anyhow::anyhow;
+use ark_bls12_381::G1Projective;
+use num_bigint::BigInt;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+const MAX_FRAME: usize = 1 << 30;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Upload {
+    pub commitments: CommitmentMatrix,
+    pub randomness_commitment: Commitment,
+    pub range_proofs: Vec<RangeProof>,
+    pub norm_proofs: Vec<NormProof>,
+    pub stats_proofs: Vec<StatsProof>,
+    pub signature: Signature,
+    pub consistency_proof: ConsistencyProof,
+    pub masked_randomness: BigInt,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Message {
+    GetParams,
+    Params {
+        mkhs: Mkhs,
+        schema: Schema,
+        norm_bound: Option<NormBound>,
+        stats: Option<StatsEncoder>,
+    },
+    Register {
+        id: u64,
+        pk: PK,
+        masking_key: G1Projective,
+    },
+    GetPeers,
+    Peers(HashMap<u64, G1Projective>),
+    Upload(Submission),
+    GetResult,
+    Result {
+        result: AggregationResult,
+        randomness: BigInt,
+        pks: HashMap<u64, PK>,
+    },
+    Ack,
+    NotReady,
+    Error(String),
+}
+
+impl Encode for Upload {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.commitments.encode(buf);
+        self.randomness_commitment.encode(buf);
+        self.range_proofs.encode(buf);
+        self.norm_proofs.encode(buf);
+        self.stats_proofs.encode(buf);
+        self.signature.encode(buf);
+        self.consistency_proof.encode(buf);
+        self.masked_randomness.encode(buf);
+    }
+}
+
+impl Decode for Upload {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            commitments: CommitmentMatrix::decode(buf)?,
+            randomness_commitment: Commitment::decode(buf)?,
+            range_proofs: Vec::decode(buf)?,
+            norm_proofs: Vec::decode(buf)?,
+            stats_proofs: Vec::decode(buf)?,
+            signature: Signature::decode(buf)?,
+            consistency_proof: ConsistencyProof::decode(buf)?,
+            masked_randomness: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Message {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::GetParams => 0u8.encode(buf),
+            Self::Params {
+                mkhs,
+                schema,
+                norm_bound,
+                stats,
+            } => {
+                1u8.encode(buf);
+                mkhs.encode(buf);
+                schema.encode(buf);
+                norm_bound.encode(buf);
+                stats.encode(buf);
+            }
+            Self::Register {
+                id,
+                pk,
+                masking_key,
+            } => {
+                2u8.encode(buf);
+                id.encode(buf);
+                pk.encode(buf);
+                masking_key.encode(buf);
+            }
+            Self::GetPeers => 3u8.encode(buf),
+            Self::Peers(peers) => {
+                4u8.encode(buf);
+                peers.encode(buf);
+            }
+            Self::Upload(submission) => {
+                5u8.encode(buf);
+                submission.encode(buf);
+            }
+            Self::GetResult => 6u8.encode(buf),
+            Self::Result {
+                result,
+                randomness,
+                pks,
+            } => {
+                7u8.encode(buf);
+                result.encode(buf);
+                randomness.encode(buf);
+                pks.encode(buf);
+            }
+            Self::Ack => 8u8.encode(buf),
+            Self::NotReady => 9u8.encode(buf),
+            Self::Error(e) => {
+                10u8.encode(buf);
+                e.encode(buf);
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(match u8::decode(buf)? {
+            0 => Self::GetParams,
+            1 => Self::Params {
+                mkhs: Mkhs::decode(buf)?,
+                schema: Schema::decode(buf)?,
+                norm_bound: Option::decode(buf)?,
+                stats: Option::decode(buf)?,
+            },
+            2 => Self::Register {
+                id: u64::decode(buf)?,
+                pk: PK::decode(buf)?,
+                masking_key: G1Projective::decode(buf)?,
+            },
+            3 => Self::GetPeers,
+            4 => Self::Peers(HashMap::decode(buf)?),
+            5 => Self::Upload(Submission::decode(buf)?),
+            6 => Self::GetResult,
+            7 => Self::Result {
+                result: AggregationResult::decode(buf)?,
+                randomness: BigInt::decode(buf)?,
+                pks: HashMap::decode(buf)?,
+            },
+            8 => Self::Ack,
+            9 => Self::NotReady,
+            10 => Self::Error(String::decode(buf)?),
+            tag => return Err(anyhow!("Unknown message {}.", tag)),
+        })
+    }
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> anyhow::Result<()> {
+    let payload = message.to_bytes();
+    if payload.len() > MAX_FRAME {
+        return Err(anyhow!("Message too large."));
+    }
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_message<R: Read>(reader: &mut R) -> anyhow::Result<Message> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(anyhow!("Message too large."));
+    }
+    // Grows with the bytes actually received rather than the claimed length.
+    let mut payload = Vec::new();
+    reader.by_ref().take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() != len {
+        return Err(anyhow!("Message truncated."));
+    }
+    Message::from_bytes(&payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::norm_proof::Norm;
+
+    #[test]
+    fn test_framing() {
+        let mkhs = Mkhs::setup(2);
+        let messages = [
+            Message::GetParams,
+            Message::Params {
+                mkhs,
+                schema: Schema::build(2, 4),
+                norm_bound: Some(NormBound::new(Norm::L2, BigInt::from(0), BigInt::from(9))),
+                stats: None,
+            },
+            Message::Error("Round closed.".to_string()),
+        ];
+
+        let mut buf = vec![];
+        for message in messages.iter() {
+            write_message(&mut buf, message).unwrap();
+        }
+        let mut reader = buf.as_slice();
+        for message in messages.iter() {
+            assert_eq!(&read_message(&mut reader).unwrap(), message);
+        }
+        assert!(read_message(&mut reader).is_err());
+
+        let mut truncated = (1u32 << 29).to_le_bytes().to_vec();
+        truncated.push(0);
+        assert!(read_message(&mut truncated.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::primitives::mkhs::{Mkhs, Signature, PK};
+use crate::util::codec::Encode;
+use anyhow::anyhow;
+use ark_bls12_381::Fr;
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use rand::thread_rng;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Certificate binding `client_id` to `pk` from round `valid_from` on,
+/// signed by the coordinator.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Registration {
+    pub client_id: u64,
+    pub pk: PK,
+    pub valid_from: u64,
+    pub(crate) sig: ed25519_dalek::Signature,
+}
+
+/// Revokes the key `client_id` had registered before round `from`, for
+/// every round from `from` on.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Revocation {
+    pub client_id: u64,
+    pub from: u64,
+    pub(crate) sig: ed25519_dalek::Signature,
+}
+
+/// Holder of the key that signs registrations and revocations.
+#[derive(Debug, Clone)]
+pub struct Coordinator {
+    sk: SigningKey,
+}
+
+impl Coordinator {
+    pub fn generate() -> Self {
+        Self {
+            sk: SigningKey::generate(&mut thread_rng()),
+        }
+    }
+
+    pub fn public(&self) -> VerifyingKey {
+        self.sk.verifying_key()
+    }
+
+    pub fn register(&self, client_id: u64, pk: PK, valid_from: u64) -> Registration {
+        let sig = self
+            .sk
+            .sign(&registration_digest(client_id, &pk, valid_from));
+        Registration {
+            client_id,
+            pk,
+            valid_from,
+            sig,
+        }
+    }
+
+    pub fn revoke(&self, client_id: u64, from: u64) -> Revocation {
+        let sig = self.sk.sign(&revocation_digest(client_id, from));
+        Revocation {
+            client_id,
+            from,
+            sig,
+        }
+    }
+}
+
+/// Public keys accepted for each round, as certified by the coordinator.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    coordinator: VerifyingKey,
+    registrations: HashMap<u64, Vec<Registration>>,
+    revocations: HashMap<u64, Vec<u64>>,
+}
+
+impl Registry {
+    pub fn new(coordinator: VerifyingKey) -> Self {
+        Self {
+            coordinator,
+            registrations: HashMap::new(),
+            revocations: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, registration: Registration) -> anyhow::Result<()> {
+        self.coordinator
+            .verify(
+                &registration_digest(
+                    registration.client_id,
+                    &registration.pk,
+                    registration.valid_from,
+                ),
+                &registration.sig,
+            )
+            .map_err(|_| {
+                anyhow!(
+                    "Registration of client {} is not signed by the coordinator.",
+                    registration.client_id
+                )
+            })?;
+        let registrations = self
+            .registrations
+            .entry(registration.client_id)
+            .or_default();
+        if registrations
+            .iter()
+            .any(|el| el.valid_from == registration.valid_from)
+        {
+            return Err(anyhow!(
+                "Client {} already has a key registered from round {}.",
+                registration.client_id,
+                registration.valid_from
+            ));
+        }
+        registrations.push(registration);
+        registrations.sort_by_key(|el| el.valid_from);
+        Ok(())
+    }
+
+    pub fn revoke(&mut self, revocation: &Revocation) -> anyhow::Result<()> {
+        self.coordinator
+            .verify(
+                &revocation_digest(revocation.client_id, revocation.from),
+                &revocation.sig,
+            )
+            .map_err(|_| {
+                anyhow!(
+                    "Revocation of client {} is not signed by the coordinator.",
+                    revocation.client_id
+                )
+            })?;
+        self.revocations
+            .entry(revocation.client_id)
+            .or_default()
+            .push(revocation.from);
+        Ok(())
+    }
+
+    /// The key registered for `client_id` in `round`: the latest one valid
+    /// by then, unless a revocation issued after it applies.
+    pub fn pk(&self, client_id: u64, round: u64) -> Option<&PK> {
+        let registration = self
+            .registrations
+            .get(&client_id)?
+            .iter()
+            .rev()
+            .find(|el| el.valid_from <= round)?;
+        let revoked = self.revocations.get(&client_id).is_some_and(|revocations| {
+            revocations
+                .iter()
+                .any(|from| registration.valid_from < *from && *from <= round)
+        });
+        (!revoked).then_some(&registration.pk)
+    }
+
+    pub fn pks(&self, round: u64) -> HashMap<u64, PK> {
+        self.registrations
+            .keys()
+            .filter_map(|id| Some((*id, self.pk(*id, round)?.clone())))
+            .collect()
+    }
+
+    /// Verifies `signature` against the keys registered for `round`,
+    /// rejecting it if it is not bound to `round` or if any of its signers
+    /// is not registered then.
+    pub fn verify(
+        &self,
+        mkhs: &Mkhs,
+        round: u64,
+        messages: &[Fr],
+        signature: &Signature,
+    ) -> anyhow::Result<()> {
+        mkhs.verify_label(signature, round)?;
+        let pks = signature
+            .lams
+            .iter()
+            .map(|lam| {
+                let pk = self.pk(lam.client_id, round).ok_or_else(|| {
+                    anyhow!(
+                        "Client {} is not registered for round {}.",
+                        lam.client_id,
+                        round
+                    )
+                })?;
+                Ok((lam.client_id, pk.clone()))
+            })
+            .collect::<anyhow::Result<HashMap<u64, PK>>>()?;
+        mkhs.verify(&pks, messages, signature)
+    }
+}
+
+fn registration_digest(client_id: u64, pk: &PK, valid_from: u64) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(b"viper-registration")
+        .chain_update(client_id.to_le_bytes())
+        .chain_update(valid_from.to_le_bytes())
+        .chain_update(pk.to_bytes())
+        .finalize()
+        .to_vec()
+}
+
+fn revocation_digest(client_id: u64, from: u64) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(b"viper-revocation")
+        .chain_update(client_id.to_le_bytes())
+        .chain_update(from.to_le_bytes())
+        .finalize()
+        .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let mkhs = Mkhs::setup(2);
+        let coordinator = Coordinator::generate();
+        let mut registry = Registry::new(coordinator.public());
+        let messages = vec![Fr::from(3), Fr::from(4)];
+
+        let old = mkhs.generate_keys(1);
+        let new = mkhs.generate_keys(1);
+        let other = mkhs.generate_keys(2);
+        registry
+            .register(coordinator.register(1, old.pk.clone(), 0))
+            .unwrap();
+        registry
+            .register(coordinator.register(2, other.pk.clone(), 3))
+            .unwrap();
+
+        // Only coordinator-signed registrations are accepted.
+        let rogue = Coordinator::generate().register(1, new.pk.clone(), 5);
+        assert!(registry.register(rogue).is_err());
+
+        let sign = |sk, round| mkhs.sign_labeled(sk, round, &messages).unwrap();
+        assert!(registry
+            .verify(&mkhs, 1, &messages, &sign(&old.sk, 1))
+            .is_ok());
+        assert!(registry
+            .verify(&mkhs, 2, &messages, &sign(&other.sk, 2))
+            .is_err());
+        assert!(registry
+            .verify(&mkhs, 3, &messages, &sign(&other.sk, 3))
+            .is_ok());
+
+        // Signatures are only accepted for the round they are bound to.
+        assert!(registry
+            .verify(&mkhs, 2, &messages, &sign(&old.sk, 1))
+            .is_err());
+
+        // A revoked key stops verifying, until a new one is registered.
+        registry.revoke(&coordinator.revoke(1, 4)).unwrap();
+        assert!(registry
+            .verify(&mkhs, 3, &messages, &sign(&old.sk, 3))
+            .is_ok());
+        assert!(registry
+            .verify(&mkhs, 4, &messages, &sign(&old.sk, 4))
+            .is_err());
+        assert_eq!(registry.pks(4).len(), 1);
+
+        registry
+            .register(coordinator.register(1, new.pk.clone(), 5))
+            .unwrap();
+        assert!(registry
+            .verify(&mkhs, 5, &messages, &sign(&old.sk, 5))
+            .is_err());
+        assert!(registry
+            .verify(&mkhs, 5, &messages, &sign(&new.sk, 5))
+            .is_ok());
+        assert!(registry
+            .verify(&mkhs, 4, &messages, &sign(&new.sk, 4))
+            .is_err());
+    }
+}
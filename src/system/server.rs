@@ -0,0 +1,458 @@
+use crate::primitives::mkhs::{Mkhs, PK};
+use crate::primitives::norm_proof::NormBound;
+use crate::system::aggregator::{AggregationResult, Aggregator};
+use crate::system::protocol::{read_message, write_message, Message, Upload};
+use crate::system::registry::Registry;
+use crate::system::stats::StatsEncoder;
+use crate::util::schema::Schema;
+use anyhow::anyhow;
+use ark_bls12_381::G1Projective;
+use num_bigint::BigInt;
+use std::collections::{BTreeSet, HashMap};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Default)]
+struct State {
+    pks: HashMap<u64, PK>,
+    masking_keys: HashMap<u64, G1Projective>,
+    uploads: HashMap<u64, Upload>,
+    result: Option<(AggregationResult, BigInt)>,
+}
+
+pub struct Server {
+    mkhs: Mkhs,
+    schema: Schema,
+    n_client: usize,
+    norm_bound: Option<NormBound>,
+    stats: Option<StatsEncoder>,
+    registry: Option<(Registry, u64)>,
+    timeout: Duration,
+    state: Mutex<State>,
+}
+
+impl Server {
+    pub fn new(mkhs: Mkhs, schema: Schema, n_client: usize) -> Self {
+        Self {
+            mkhs,
+            schema,
+            n_client,
+            norm_bound: None,
+            stats: None,
+            registry: None,
+            timeout: Duration::from_secs(30),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Requires every upload to prove its rows are within `bound`. Clients
+    /// get the bound with the parameters.
+    pub fn with_norm_bound(mut self, bound: NormBound) -> anyhow::Result<Self> {
+        bound.check(self.schema.len())?;
+        self.norm_bound = Some(bound);
+        Ok(self)
+    }
+
+    /// Requires every upload to hold rows encoded by `encoder` and to prove
+    /// they are well formed. Clients get the encoder with the parameters.
+    pub fn with_stats(mut self, encoder: StatsEncoder) -> anyhow::Result<Self> {
+        if encoder.schema() != &self.schema {
+            return Err(anyhow!("Server schema is not the statistics schema."));
+        }
+        if self.n_client > encoder.n_client() {
+            return Err(anyhow!(
+                "Statistics support at most {} clients, got {}.",
+                encoder.n_client(),
+                self.n_client
+            ));
+        }
+        self.stats = Some(encoder);
+        Ok(self)
+    }
+
+    /// Bounds how long a connection may take to send its request or read
+    /// the response, so a stalled peer does not hold its thread forever.
+    /// Defaults to 30 seconds.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Serves each connection on its own thread. A connection that fails is
+    /// answered with [`Message::Error`] when possible and then dropped.
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.handle_connection(stream));
+        }
+        Ok(())
+    }
+
+    /// Only accepts the keys `registry` certifies for `round`, instead of
+    /// any key a client registers with.
+    pub fn with_registry(self, registry: Registry, round: u64) -> Self {
+        Self {
+            registry: Some((registry, round)),
+            ..self
+        }
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let response = match read_message(&mut stream) {
+            Ok(request) => self
+                .handle(request)
+                .unwrap_or_else(|e| Message::Error(e.to_string())),
+            Err(e) => {
+                let _ = write_message(&mut stream, &Message::Error(e.to_string()));
+                return Err(e);
+            }
+        };
+        write_message(&mut stream, &response)
+    }
+
+    pub fn handle(&self, request: Message) -> anyhow::Result<Message> {
+        match request {
+            Message::GetParams => Ok(Message::Params {
+                mkhs: self.mkhs.clone(),
+                schema: self.schema.clone(),
+                norm_bound: self.norm_bound.clone(),
+                stats: self.stats.clone(),
+            }),
+            Message::Register {
+                id,
+                pk,
+                masking_key,
+            } => {
+                if let Some((registry, round)) = &self.registry {
+                    if registry.pk(id, *round) != Some(&pk) {
+                        return Err(anyhow!(
+                            "Key of client {} is not registered for round {}.",
+                            id,
+                            round
+                        ));
+                    }
+                }
+                let mut state = self.state.lock().unwrap();
+                if state.pks.contains_key(&id) {
+                    return Err(anyhow!("Client {} already registered.", id));
+                }
+                if state.pks.len() == self.n_client {
+                    return Err(anyhow!("Registration closed."));
+                }
+                state.pks.insert(id, pk);
+                state.masking_keys.insert(id, masking_key);
+                Ok(Message::Ack)
+            }
+            Message::GetPeers => {
+                let state = self.state.lock().unwrap();
+                if state.masking_keys.len() < self.n_client {
+                    return Ok(Message::NotReady);
+                }
+                Ok(Message::Peers(state.masking_keys.clone()))
+            }
+            Message::Upload(submission) => {
+                // The proofs are verified without holding the lock, so that
+                // uploads of different clients are verified concurrently.
+                let id = submission.client_id;
+                let pk = {
+                    let state = self.state.lock().unwrap();
+                    Self::check_upload(&state, id)?;
+                    state.pks[&id].clone()
+                };
+                if submission.round != self.round() {
+                    return Err(anyhow!(
+                        "Submission for round {} sent to round {}.",
+                        submission.round,
+                        self.round()
+                    ));
+                }
+                submission.verify(&pk)?;
+                let upload = submission.upload;
+                if upload.signature.participants() != BTreeSet::from([id]) {
+                    return Err(anyhow!(
+                        "Upload of client {} is signed by clients {:?}.",
+                        id,
+                        upload.signature.participants()
+                    ));
+                }
+                self.mkhs.verify_label(&upload.signature, self.round())?;
+                match &self.stats {
+                    Some(encoder) => encoder.verify(
+                        &upload.commitments,
+                        &upload.randomness_commitment,
+                        &upload.range_proofs,
+                        &upload.stats_proofs,
+                    )?,
+                    None => Aggregator::verify_range_proofs(
+                        &self.schema,
+                        &upload.commitments,
+                        &upload.randomness_commitment,
+                        &upload.range_proofs,
+                    )?,
+                }
+                Aggregator::verify_consistency_proof(
+                    &self.mkhs,
+                    id,
+                    &self.schema,
+                    &upload.commitments,
+                    &upload.randomness_commitment,
+                    &upload.signature,
+                    &upload.consistency_proof,
+                )?;
+                if let Some(bound) = &self.norm_bound {
+                    Aggregator::verify_norm_proofs(
+                        bound,
+                        &upload.commitments,
+                        &upload.randomness_commitment,
+                        &upload.norm_proofs,
+                    )?;
+                }
+                let mut state = self.state.lock().unwrap();
+                Self::check_upload(&state, id)?;
+                state.uploads.insert(id, upload);
+                Ok(Message::Ack)
+            }
+            Message::GetResult => Ok(match self.result()? {
+                Some((result, randomness, pks)) => Message::Result {
+                    result,
+                    randomness,
+                    pks,
+                },
+                None => Message::NotReady,
+            }),
+            _ => Err(anyhow!("Unexpected message.")),
+        }
+    }
+
+    /// The round uploads are signed for: the registry's round, or 0.
+    fn round(&self) -> u64 {
+        self.registry.as_ref().map_or(0, |(_, round)| *round)
+    }
+
+    fn check_upload(state: &State, id: u64) -> anyhow::Result<()> {
+        if !state.pks.contains_key(&id) {
+            return Err(anyhow!("Client {} not registered.", id));
+        }
+        if state.uploads.contains_key(&id) {
+            return Err(anyhow!("Client {} already uploaded.", id));
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn result(&self) -> anyhow::Result<Option<(AggregationResult, BigInt, HashMap<u64, PK>)>> {
+        let mut state = self.state.lock().unwrap();
+        if state.uploads.len() < self.n_client {
+            return Ok(None);
+        }
+        if state.result.is_none() {
+            let uploads: Vec<&Upload> = state.uploads.values().collect();
+            let commitments: Vec<_> = uploads.iter().map(|el| el.commitments.clone()).collect();
+            let randomness_commitments: Vec<_> = uploads
+                .iter()
+                .map(|el| el.randomness_commitment.clone())
+                .collect();
+            let signatures: Vec<_> = uploads.iter().map(|el| el.signature.clone()).collect();
+            let masked: Vec<_> = uploads
+                .iter()
+                .map(|el| el.masked_randomness.clone())
+                .collect();
+
+            let randomness = Aggregator::unmask_randomness(&masked);
+            let result = Aggregator::aggregate(
+                &self.mkhs,
+                &self.schema,
+                &commitments,
+                &signatures,
+                &randomness_commitments,
+                &randomness,
+            )?;
+            state.result = Some((result, randomness));
+        }
+        let (result, randomness) = state.result.clone().unwrap();
+        Ok(Some((result, randomness, state.pks.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::commitment::Commitment;
+    use crate::primitives::norm_proof::Norm;
+    use crate::system::client::Client;
+    use crate::system::masking::MaskingKey;
+    use crate::system::registry::Coordinator;
+    use crate::system::session::Submission;
+    use crate::util::dataset::Dataset;
+
+    #[test]
+    fn test_malformed_upload() {
+        let schema = Schema::build(1, 2);
+        let mkhs = Mkhs::setup(schema.len());
+        let server = Server::new(mkhs.clone(), schema.clone(), 1);
+
+        let dataset = Dataset::with_schema(&schema, &[vec![BigInt::from(5)]]).unwrap();
+        let client = Client::new(1, mkhs.generate_keys(1), dataset, BigInt::from(7));
+        let masking_key = MaskingKey::generate();
+        server
+            .handle(Message::Register {
+                id: 1,
+                pk: client.key_pair.pk.clone(),
+                masking_key: masking_key.public,
+            })
+            .unwrap();
+
+        let peers = HashMap::from([(1, masking_key.public)]);
+        let upload = client.upload(&mkhs, &masking_key, &peers, None).unwrap();
+        let sign = |upload| Message::Upload(Submission::sign(&client.key_pair.sk, 0, upload));
+        let mut malformed = upload.clone();
+        malformed.randomness_commitment = Commitment::commit_randomness(&BigInt::from(1));
+        assert!(server.handle(sign(malformed)).is_err());
+
+        server.handle(sign(upload.clone())).unwrap();
+        assert!(server.handle(sign(upload)).is_err());
+        assert!(matches!(
+            server.handle(Message::GetResult).unwrap(),
+            Message::Result { .. }
+        ));
+    }
+
+    #[test]
+    fn test_unauthenticated_upload() {
+        let schema = Schema::build(1, 2);
+        let mkhs = Mkhs::setup(schema.len());
+        let server = Server::new(mkhs.clone(), schema.clone(), 2);
+
+        let clients: Vec<Client> = (1..=2)
+            .map(|id| {
+                let dataset = Dataset::with_schema(&schema, &[vec![BigInt::from(5)]]).unwrap();
+                Client::new(id, mkhs.generate_keys(id), dataset, BigInt::from(7))
+            })
+            .collect();
+        let masking_keys: Vec<MaskingKey> =
+            clients.iter().map(|_| MaskingKey::generate()).collect();
+        for (client, masking_key) in clients.iter().zip(masking_keys.iter()) {
+            server
+                .handle(Message::Register {
+                    id: client.id,
+                    pk: client.key_pair.pk.clone(),
+                    masking_key: masking_key.public,
+                })
+                .unwrap();
+        }
+        let peers: HashMap<u64, G1Projective> = clients
+            .iter()
+            .zip(masking_keys.iter())
+            .map(|(client, key)| (client.id, key.public))
+            .collect();
+
+        // Client 2's upload, tagged with client 2's key but claimed by client 1.
+        let upload = clients[1]
+            .upload(&mkhs, &masking_keys[1], &peers, None)
+            .unwrap();
+        let mut stolen = Submission::sign(&clients[1].key_pair.sk, 0, upload.clone());
+        stolen.client_id = 1;
+        assert!(server.handle(Message::Upload(stolen)).is_err());
+
+        // Client 2's upload, tagged by client 1.
+        let forwarded = Submission::sign(&clients[0].key_pair.sk, 0, upload);
+        assert_eq!(
+            server
+                .handle(Message::Upload(forwarded))
+                .unwrap_err()
+                .to_string(),
+            "Upload of client 1 is signed by clients {2}."
+        );
+
+        // Uploads are signed for the server's round.
+        let upload = clients[0]
+            .upload(&mkhs, &masking_keys[0], &peers, None)
+            .unwrap();
+        let late = Submission::sign(&clients[0].key_pair.sk, 1, upload.clone());
+        assert!(server.handle(Message::Upload(late)).is_err());
+        let submission = Submission::sign(&clients[0].key_pair.sk, 0, upload);
+        server.handle(Message::Upload(submission)).unwrap();
+    }
+
+    #[test]
+    fn test_norm_bound() {
+        let schema = Schema::build(1, 2);
+        let mkhs = Mkhs::setup(schema.len());
+        let bound = NormBound::new(Norm::LInf, BigInt::from(0), BigInt::from(10));
+        let server = Server::new(mkhs.clone(), schema.clone(), 1)
+            .with_norm_bound(bound.clone())
+            .unwrap();
+        let Message::Params { norm_bound, .. } = server.handle(Message::GetParams).unwrap() else {
+            panic!("Unexpected response to GetParams.");
+        };
+        assert_eq!(norm_bound, Some(bound.clone()));
+
+        let dataset = Dataset::with_schema(&schema, &[vec![BigInt::from(5)]]).unwrap();
+        let client = Client::new(1, mkhs.generate_keys(1), dataset, BigInt::from(7));
+        let masking_key = MaskingKey::generate();
+        server
+            .handle(Message::Register {
+                id: 1,
+                pk: client.key_pair.pk.clone(),
+                masking_key: masking_key.public,
+            })
+            .unwrap();
+
+        let peers = HashMap::from([(1, masking_key.public)]);
+        let unproven = client
+            .submission(&mkhs, &masking_key, &peers, None)
+            .unwrap();
+        assert!(server.handle(Message::Upload(unproven)).is_err());
+
+        let submission = client
+            .submission(&mkhs, &masking_key, &peers, Some(&bound))
+            .unwrap();
+        server.handle(Message::Upload(submission)).unwrap();
+    }
+
+    #[test]
+    fn test_registry() {
+        let schema = Schema::build(1, 2);
+        let mkhs = Mkhs::setup(schema.len());
+        let coordinator = Coordinator::generate();
+        let key_pair = mkhs.generate_keys(1);
+        let mut registry = Registry::new(coordinator.public());
+        registry
+            .register(coordinator.register(1, key_pair.pk.clone(), 0))
+            .unwrap();
+        let server = Server::new(mkhs.clone(), schema, 2).with_registry(registry, 0);
+
+        let register = |id, pk| {
+            server.handle(Message::Register {
+                id,
+                pk,
+                masking_key: MaskingKey::generate().public,
+            })
+        };
+        assert!(register(1, mkhs.generate_keys(1).pk).is_err());
+        assert!(register(2, mkhs.generate_keys(2).pk).is_err());
+        assert!(register(1, key_pair.pk).is_ok());
+    }
+
+    #[test]
+    fn test_stalled_connection() {
+        let schema = Schema::build(1, 2);
+        let server = Server::new(Mkhs::setup(schema.len()), schema, 1)
+            .with_timeout(Duration::from_millis(50));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        // The peer never sends its request.
+        assert!(server.handle_connection(stream).is_err());
+        assert!(matches!(
+            read_message(&mut peer).unwrap(),
+            Message::Error(_)
+        ));
+    }
+}
@@ -0,0 +1,581 @@
+use crate::primitives::mkhs::{Mkhs, PK, SK};
+use crate::primitives::norm_proof::NormBound;
+use crate::system::aggregator::{AggregationResult, Aggregator};
+use crate::system::protocol::Upload;
+use crate::system::registry::Registry;
+use crate::system::stats::StatsEncoder;
+use crate::util::codec::Encode;
+use crate::util::schema::Schema;
+use anyhow::anyhow;
+use ed25519_dalek::{Signer, Verifier};
+use num_bigint::BigInt;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// A client upload bound to a round: the ed25519 tag covers the round id and
+/// the whole upload, so it cannot be replayed into another round.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Submission {
+    pub client_id: u64,
+    pub round: u64,
+    pub upload: Upload,
+    pub tag: ed25519_dalek::Signature,
+}
+
+impl Submission {
+    pub fn sign(sk: &SK, round: u64, upload: Upload) -> Self {
+        let tag = sk.sk_sig.sign(&digest(sk.k, round, &upload));
+        Self {
+            client_id: sk.k,
+            round,
+            upload,
+            tag,
+        }
+    }
+
+    pub fn verify(&self, pk: &PK) -> anyhow::Result<()> {
+        pk.pk_sig
+            .verify(&digest(self.client_id, self.round, &self.upload), &self.tag)
+            .map_err(|_| anyhow!("Invalid submission tag from client {}.", self.client_id))
+    }
+}
+
+fn digest(client_id: u64, round: u64, upload: &Upload) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(client_id.to_le_bytes())
+        .chain_update(round.to_le_bytes())
+        .chain_update(upload.to_bytes())
+        .finalize()
+        .to_vec()
+}
+
+#[derive(Debug, Clone)]
+pub struct Round {
+    pub id: u64,
+    pub participants: BTreeSet<u64>,
+    pub deadline: Instant,
+    submissions: BTreeMap<u64, Submission>,
+    late: BTreeSet<u64>,
+}
+
+impl Round {
+    pub fn submitted(&self) -> Vec<u64> {
+        self.submissions.keys().copied().collect()
+    }
+
+    pub fn missing(&self) -> Vec<u64> {
+        self.participants
+            .iter()
+            .filter(|id| !self.submissions.contains_key(id))
+            .copied()
+            .collect()
+    }
+
+    pub fn late(&self) -> Vec<u64> {
+        self.late.iter().copied().collect()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() > self.deadline
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RoundResult {
+    pub round: u64,
+    pub contributors: Vec<u64>,
+    pub missing: Vec<u64>,
+    pub late: Vec<u64>,
+    pub result: AggregationResult,
+    pub randomness: BigInt,
+}
+
+pub struct Session {
+    mkhs: Mkhs,
+    schema: Schema,
+    pks: HashMap<u64, PK>,
+    round_duration: Duration,
+    norm_bound: Option<NormBound>,
+    stats: Option<StatsEncoder>,
+    registry: Option<Registry>,
+    last_round: u64,
+    current: Option<Round>,
+}
+
+impl Session {
+    pub fn new(
+        mkhs: Mkhs,
+        schema: Schema,
+        pks: HashMap<u64, PK>,
+        round_duration: Duration,
+    ) -> Self {
+        Self {
+            mkhs,
+            schema,
+            pks,
+            round_duration,
+            norm_bound: None,
+            stats: None,
+            registry: None,
+            last_round: 0,
+            current: None,
+        }
+    }
+
+    /// Requires every submission to prove its rows are within `bound`.
+    pub fn with_norm_bound(mut self, bound: NormBound) -> anyhow::Result<Self> {
+        bound.check(self.schema.len())?;
+        self.norm_bound = Some(bound);
+        Ok(self)
+    }
+
+    pub fn norm_bound(&self) -> Option<&NormBound> {
+        self.norm_bound.as_ref()
+    }
+
+    /// Requires every submission to hold rows encoded by `encoder` and to
+    /// prove they are well formed, and caps rounds at the encoder's number
+    /// of clients.
+    pub fn with_stats(mut self, encoder: StatsEncoder) -> anyhow::Result<Self> {
+        if encoder.schema() != &self.schema {
+            return Err(anyhow!("Session schema is not the statistics schema."));
+        }
+        self.stats = Some(encoder);
+        Ok(self)
+    }
+
+    pub fn stats(&self) -> Option<&StatsEncoder> {
+        self.stats.as_ref()
+    }
+
+    /// Takes the keys of every round from `registry` instead of the fixed
+    /// ones, so rotations and revocations apply from the round they name.
+    pub fn with_registry(self, registry: Registry) -> Self {
+        Self {
+            registry: Some(registry),
+            ..self
+        }
+    }
+
+    /// The registry, to register or revoke keys between rounds.
+    pub fn registry_mut(&mut self) -> Option<&mut Registry> {
+        self.registry.as_mut()
+    }
+
+    pub fn current(&self) -> Option<&Round> {
+        self.current.as_ref()
+    }
+
+    pub fn start_round(&mut self, participants: &[u64]) -> anyhow::Result<u64> {
+        if let Some(round) = &self.current {
+            return Err(anyhow!("Round {} is still open.", round.id));
+        }
+        if participants.is_empty() {
+            return Err(anyhow!("A round needs at least one participant."));
+        }
+        if let Some(encoder) = &self.stats {
+            if participants.len() > encoder.n_client() {
+                return Err(anyhow!(
+                    "Statistics support at most {} clients per round.",
+                    encoder.n_client()
+                ));
+            }
+        }
+        if let Some(registry) = &self.registry {
+            self.pks = registry.pks(self.last_round + 1);
+        }
+        if let Some(id) = participants.iter().find(|id| !self.pks.contains_key(id)) {
+            return Err(anyhow!("Client {} not registered.", id));
+        }
+
+        self.last_round += 1;
+        self.current = Some(Round {
+            id: self.last_round,
+            participants: participants.iter().copied().collect(),
+            deadline: Instant::now() + self.round_duration,
+            submissions: BTreeMap::new(),
+            late: BTreeSet::new(),
+        });
+        Ok(self.last_round)
+    }
+
+    pub fn submit(&mut self, submission: Submission) -> anyhow::Result<()> {
+        let round = self
+            .current
+            .as_mut()
+            .ok_or_else(|| anyhow!("No open round."))?;
+        let id = submission.client_id;
+
+        if submission.round != round.id {
+            return Err(anyhow!(
+                "Submission for round {} sent to round {}.",
+                submission.round,
+                round.id
+            ));
+        }
+        if !round.participants.contains(&id) {
+            return Err(anyhow!("Client {} is not part of round {}.", id, round.id));
+        }
+        if round.submissions.contains_key(&id) {
+            return Err(anyhow!("Client {} already submitted.", id));
+        }
+        submission.verify(&self.pks[&id])?;
+        if round.is_expired() {
+            round.late.insert(id);
+            return Err(anyhow!(
+                "Client {} missed the round {} deadline.",
+                id,
+                round.id
+            ));
+        }
+
+        self.mkhs
+            .verify_label(&submission.upload.signature, round.id)?;
+        let upload = &submission.upload;
+        match &self.stats {
+            Some(encoder) => encoder.verify(
+                &upload.commitments,
+                &upload.randomness_commitment,
+                &upload.range_proofs,
+                &upload.stats_proofs,
+            )?,
+            None => Aggregator::verify_range_proofs(
+                &self.schema,
+                &upload.commitments,
+                &upload.randomness_commitment,
+                &upload.range_proofs,
+            )?,
+        }
+        Aggregator::verify_consistency_proof(
+            &self.mkhs,
+            id,
+            &self.schema,
+            &upload.commitments,
+            &upload.randomness_commitment,
+            &upload.signature,
+            &upload.consistency_proof,
+        )?;
+        if let Some(bound) = &self.norm_bound {
+            Aggregator::verify_norm_proofs(
+                bound,
+                &upload.commitments,
+                &upload.randomness_commitment,
+                &upload.norm_proofs,
+            )?;
+        }
+        if let Some(first) = round.submissions.values().next() {
+            upload
+                .commitments
+                .check_shape(first.upload.commitments.rows, first.upload.commitments.cols)?;
+        }
+
+        round.submissions.insert(id, submission);
+        Ok(())
+    }
+
+    /// Closes the current round without aggregating it, e.g. once it has
+    /// expired with no submissions, and returns it.
+    pub fn abort_round(&mut self) -> anyhow::Result<Round> {
+        self.current.take().ok_or_else(|| anyhow!("No open round."))
+    }
+
+    /// Aggregates the current round and closes it. `corrections` carries any
+    /// extra masked randomness (dropout corrections or recovered randomness)
+    /// needed for the masks of missing clients to cancel.
+    pub fn close_round(&mut self, corrections: &[BigInt]) -> anyhow::Result<RoundResult> {
+        let round = self
+            .current
+            .as_ref()
+            .ok_or_else(|| anyhow!("No open round."))?;
+        if round.submissions.is_empty() {
+            return Err(anyhow!("Round {} has no submissions.", round.id));
+        }
+
+        let uploads: Vec<&Upload> = round.submissions.values().map(|el| &el.upload).collect();
+        let commitments: Vec<_> = uploads.iter().map(|el| el.commitments.clone()).collect();
+        let randomness_commitments: Vec<_> = uploads
+            .iter()
+            .map(|el| el.randomness_commitment.clone())
+            .collect();
+        let signatures: Vec<_> = uploads.iter().map(|el| el.signature.clone()).collect();
+        let mut masked: Vec<_> = uploads
+            .iter()
+            .map(|el| el.masked_randomness.clone())
+            .collect();
+        masked.extend_from_slice(corrections);
+
+        let randomness = Aggregator::unmask_randomness(&masked);
+        let result = Aggregator::aggregate(
+            &self.mkhs,
+            &self.schema,
+            &commitments,
+            &signatures,
+            &randomness_commitments,
+            &randomness,
+        )?;
+
+        let round = self.current.take().unwrap();
+        Ok(RoundResult {
+            round: round.id,
+            contributors: round.submitted(),
+            missing: round.missing(),
+            late: round.late(),
+            result,
+            randomness,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::commitment::Commitment;
+    use crate::system::client::Client;
+    use crate::system::masking::MaskingKey;
+    use crate::system::registry::Coordinator;
+    use crate::util::dataset::Dataset;
+    use ark_bls12_381::G1Projective;
+
+    fn setup(
+        ids: &[u64],
+    ) -> (
+        Mkhs,
+        Vec<Client>,
+        Vec<MaskingKey>,
+        HashMap<u64, G1Projective>,
+    ) {
+        let mkhs = Mkhs::setup(1);
+        let clients: Vec<Client> = ids
+            .iter()
+            .map(|id| {
+                let dataset = Dataset::new(&[vec![BigInt::from(*id)]]);
+                Client::new(*id, mkhs.generate_keys(*id), dataset, BigInt::from(10 + id))
+            })
+            .collect();
+        let keys: Vec<MaskingKey> = ids.iter().map(|_| MaskingKey::generate()).collect();
+        let peers = ids
+            .iter()
+            .zip(keys.iter())
+            .map(|(id, key)| (*id, key.public))
+            .collect();
+        (mkhs, clients, keys, peers)
+    }
+
+    fn session(mkhs: &Mkhs, clients: &[Client], round_duration: Duration) -> Session {
+        let pks = clients
+            .iter()
+            .map(|el| (el.id, el.key_pair.pk.clone()))
+            .collect();
+        Session::new(mkhs.clone(), Schema::unbounded(1), pks, round_duration)
+    }
+
+    #[test]
+    fn test_replay_rejected() {
+        let (mkhs, mut clients, keys, peers) = setup(&[1, 2]);
+        let mut session = session(&mkhs, &clients, Duration::from_secs(60));
+
+        assert_eq!(session.start_round(&[1, 2]).unwrap(), 1);
+        clients[0].round = 1;
+        let old = clients[0]
+            .submission(&mkhs, &keys[0], &peers, None)
+            .unwrap();
+        session.submit(old.clone()).unwrap();
+        assert!(session.submit(old.clone()).is_err());
+        assert!(session.start_round(&[1, 2]).is_err());
+
+        assert_eq!(session.abort_round().unwrap().submitted(), vec![1]);
+        assert_eq!(session.start_round(&[1, 2]).unwrap(), 2);
+        assert!(session.submit(old.clone()).is_err());
+
+        // Relabelling the old upload breaks the submission tag.
+        let mut relabelled = old.clone();
+        relabelled.round = 2;
+        assert!(session.submit(relabelled).is_err());
+
+        // Even re-tagged by the client, round 1 signatures stay bound to round 1.
+        let retagged = Submission::sign(&clients[0].key_pair.sk, 2, old.upload);
+        assert!(session.submit(retagged).is_err());
+
+        clients[0].round = 2;
+        let fresh = clients[0]
+            .submission(&mkhs, &keys[0], &peers, None)
+            .unwrap();
+        assert!(session.submit(fresh).is_ok());
+        assert_eq!(session.current().unwrap().missing(), vec![2]);
+    }
+
+    #[test]
+    fn test_late_submission() {
+        let (mkhs, mut clients, keys, peers) = setup(&[1, 2]);
+        let mut session = session(&mkhs, &clients, Duration::ZERO);
+
+        let round = session.start_round(&[1, 2]).unwrap();
+        clients[0].round = round;
+        std::thread::sleep(Duration::from_millis(1));
+        let submission = clients[0]
+            .submission(&mkhs, &keys[0], &peers, None)
+            .unwrap();
+        assert!(session.submit(submission.clone()).is_err());
+
+        // A submission that fails its tag does not get its client marked late.
+        let forged = Submission {
+            client_id: 2,
+            ..submission
+        };
+        assert!(session.submit(forged).is_err());
+
+        let current = session.current().unwrap();
+        assert_eq!(current.late(), vec![1]);
+        assert_eq!(current.missing(), vec![1, 2]);
+        assert!(session.close_round(&[]).is_err());
+        assert!(session.start_round(&[1]).is_err());
+
+        let aborted = session.abort_round().unwrap();
+        assert_eq!(aborted.late(), vec![1]);
+        assert!(session.abort_round().is_err());
+        assert_eq!(session.start_round(&[1]).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_wrong_randomness_commitment() {
+        let (mkhs, mut clients, keys, peers) = setup(&[1]);
+        let mut session = session(&mkhs, &clients, Duration::from_secs(60));
+
+        clients[0].round = session.start_round(&[1]).unwrap();
+        let mut upload = clients[0].upload(&mkhs, &keys[0], &peers, None).unwrap();
+        upload.randomness_commitment = Commitment::commit_randomness(&BigInt::from(1));
+        let submission = Submission::sign(&clients[0].key_pair.sk, 1, upload);
+        assert!(session.submit(submission).is_err());
+        assert!(session.current().unwrap().submitted().is_empty());
+    }
+
+    #[test]
+    fn test_registry_keys() {
+        let (mkhs, mut clients, keys, peers) = setup(&[1, 2]);
+        let coordinator = Coordinator::generate();
+        let mut registry = Registry::new(coordinator.public());
+        for client in &clients {
+            registry
+                .register(coordinator.register(client.id, client.key_pair.pk.clone(), 1))
+                .unwrap();
+        }
+        let mut session = Session::new(
+            mkhs.clone(),
+            Schema::unbounded(1),
+            HashMap::new(),
+            Duration::from_secs(60),
+        )
+        .with_registry(registry);
+
+        assert_eq!(session.start_round(&[1, 2]).unwrap(), 1);
+        clients[0].round = 1;
+        let submission = clients[0]
+            .submission(&mkhs, &keys[0], &peers, None)
+            .unwrap();
+        session.submit(submission).unwrap();
+        session.abort_round().unwrap();
+
+        // Client 1 rotates its key from round 2 on and client 2 is revoked.
+        let rotated = mkhs.generate_keys(1);
+        let registry = session.registry_mut().unwrap();
+        registry
+            .register(coordinator.register(1, rotated.pk.clone(), 2))
+            .unwrap();
+        registry.revoke(&coordinator.revoke(2, 2)).unwrap();
+        assert!(session.start_round(&[1, 2]).is_err());
+        assert_eq!(session.start_round(&[1]).unwrap(), 2);
+
+        clients[0].round = 2;
+        let stale = clients[0]
+            .submission(&mkhs, &keys[0], &peers, None)
+            .unwrap();
+        assert!(session.submit(stale).is_err());
+        clients[0].key_pair = rotated;
+        let submission = clients[0]
+            .submission(&mkhs, &keys[0], &peers, None)
+            .unwrap();
+        session.submit(submission).unwrap();
+    }
+
+    #[test]
+    fn test_close_round() {
+        let (mkhs, mut clients, keys, peers) = setup(&[1, 2]);
+        let mut session = session(&mkhs, &clients, Duration::from_secs(60));
+
+        let round = session.start_round(&[1, 2]).unwrap();
+        for (client, key) in clients.iter_mut().zip(keys.iter()) {
+            client.round = round;
+            session
+                .submit(client.submission(&mkhs, key, &peers, None).unwrap())
+                .unwrap();
+        }
+
+        let result = session.close_round(&[]).unwrap();
+        assert_eq!(result.round, 1);
+        assert_eq!(result.contributors, vec![1, 2]);
+        assert!(result.missing.is_empty());
+        assert_eq!(result.result.table.rows, vec![vec![BigInt::from(3)]]);
+        assert!(session.current().is_none());
+
+        let pks = clients
+            .iter()
+            .map(|el| (el.id, el.key_pair.pk.clone()))
+            .collect();
+        assert!(Client::verify_result(&mkhs, &pks, &result.result, &result.randomness).is_ok());
+    }
+
+    #[test]
+    fn test_stats_round() {
+        let (_, clients, keys, peers) = setup(&[1, 2]);
+        let encoder = StatsEncoder::new(&Schema::build(1, 1), 1, 2, vec![]).unwrap();
+        let mkhs = Mkhs::setup(encoder.schema().len());
+        let mut clients: Vec<Client> = clients
+            .iter()
+            .map(|client| {
+                let dataset =
+                    Dataset::with_schema(encoder.source(), &[vec![BigInt::from(4)]]).unwrap();
+                let client = Client::new(
+                    client.id,
+                    mkhs.generate_keys(client.id),
+                    dataset,
+                    BigInt::from(7),
+                );
+                encoder.client(&client).unwrap()
+            })
+            .collect();
+        let pks = clients
+            .iter()
+            .map(|el| (el.id, el.key_pair.pk.clone()))
+            .collect();
+        let mut session = Session::new(
+            mkhs.clone(),
+            encoder.schema().clone(),
+            pks,
+            Duration::from_secs(60),
+        )
+        .with_stats(encoder.clone())
+        .unwrap();
+        assert!(session.start_round(&[1, 2, 3]).is_err());
+
+        let round = session.start_round(&[1, 2]).unwrap();
+        for client in clients.iter_mut() {
+            client.round = round;
+        }
+        // Uploads without statistics proofs are rejected.
+        let unproven = clients[0]
+            .submission(&mkhs, &keys[0], &peers, None)
+            .unwrap();
+        assert!(session.submit(unproven).is_err());
+        for (client, key) in clients.iter().zip(keys.iter()) {
+            let submission = encoder
+                .submission(client, &mkhs, key, &peers, None)
+                .unwrap();
+            session.submit(submission).unwrap();
+        }
+
+        let result = session.close_round(&[]).unwrap();
+        let stats = encoder.statistics(&result.result).unwrap();
+        assert_eq!(stats.count, BigInt::from(2));
+    }
+}
@@ -0,0 +1,598 @@
+use crate::primitives::commitment::{Commitment, CommitmentMatrix};
+use crate::primitives::dlog::MAX_VALUE;
+use crate::primitives::mkhs::Mkhs;
+use crate::primitives::norm_proof::NormBound;
+use crate::primitives::range_proof::RangeProof;
+use crate::primitives::stats_proof::{LinearRange, StatsProof};
+use crate::system::aggregator::{AggregationResult, Aggregator};
+use crate::system::client::Client;
+use crate::system::masking::MaskingKey;
+use crate::system::protocol::Upload;
+use crate::system::session::Submission;
+use crate::util::dataset::Dataset;
+use crate::util::schema::{Column, Schema, Table};
+use anyhow::anyhow;
+use ark_bls12_381::G1Projective;
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Buckets `[edges[i], edges[i + 1])` over one column, in encoded units.
+/// Values outside every bucket are not counted.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Histogram {
+    pub column: usize,
+    pub edges: Vec<BigInt>,
+}
+
+/// Encodes each row `x` of a client's dataset as `(1, x, x^2, one-hot)`:
+/// a presence bit, the values, their squares and, for every histogram, one
+/// bit per bucket. Datasets are padded to `max_rows` with all-zero rows, so
+/// every client commits the same shape.
+///
+/// Every row comes with a [`StatsProof`] that its squares are the squares of
+/// its values, that exactly one bucket of each histogram is set and contains
+/// the value, and that padding rows are zero. The encoded rows are verified
+/// and aggregated like any other dataset, and only the aggregated table is
+/// summed into statistics, so they are tied to the committed rows. A
+/// [`Session`] or [`Server`] built [`with_stats`](Session::with_stats)
+/// checks these proofs on every upload before aggregating it.
+///
+/// [`Session`]: crate::system::session::Session
+/// [`Server`]: crate::system::server::Server
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StatsEncoder {
+    pub(crate) source: Schema,
+    pub(crate) max_rows: usize,
+    pub(crate) n_client: usize,
+    pub(crate) histograms: Vec<Histogram>,
+    schema: Schema,
+    squares: Vec<(usize, usize)>,
+    ranges: Vec<LinearRange>,
+}
+
+/// Statistics summed from an aggregated table of encoded rows.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Statistics {
+    pub count: BigInt,
+    pub sums: Vec<BigDecimal>,
+    pub sums_of_squares: Vec<BigDecimal>,
+    pub histograms: Vec<Vec<BigInt>>,
+}
+
+impl StatsEncoder {
+    /// Statistics over datasets of `source` with at most `max_rows` rows per
+    /// client, for up to `n_client` clients.
+    ///
+    /// Each aggregated cell is opened by a discrete log bounded by
+    /// [`MAX_VALUE`] (2^40), and the square column of a value up to `max`
+    /// sums to `n_client * max^2`. This caps the number of clients at
+    /// `MAX_VALUE / max^2`, see [`StatsEncoder::max_clients`]: for values in
+    /// `[0, 1]`, 10995 clients with 4 decimals and 109 with 5. Larger
+    /// `n_client` are rejected here.
+    pub fn new(
+        source: &Schema,
+        max_rows: usize,
+        n_client: usize,
+        histograms: Vec<Histogram>,
+    ) -> anyhow::Result<Self> {
+        if let Some(histogram) = histograms.iter().find(|el| {
+            el.column >= source.len()
+                || el.edges.len() < 2
+                || el.edges.windows(2).any(|w| w[0] >= w[1])
+        }) {
+            return Err(anyhow!(
+                "Invalid histogram over column {}.",
+                histogram.column
+            ));
+        }
+        let n_col = source.len();
+        let one = || BigInt::from(1);
+        let mut columns = vec![Column::new("count", 0, BigInt::zero(), one())];
+        let mut squares = vec![];
+        let mut ranges = vec![];
+        for column in source.columns.iter() {
+            columns.push(Column::new(
+                &column.name,
+                column.decimals,
+                BigInt::zero(),
+                column.max.clone(),
+            ));
+        }
+        for (j, column) in source.columns.iter().enumerate() {
+            columns.push(Column::new(
+                &format!("sq({})", column.name),
+                2 * column.decimals,
+                BigInt::zero(),
+                &column.max * &column.max,
+            ));
+            squares.push((1 + j, 1 + n_col + j));
+
+            // x in [min, max] for a present row, x = 0 for a padding row.
+            let width = &column.max - &column.min;
+            ranges.push(LinearRange::new(
+                vec![(1 + j, one()), (0, -&column.min)],
+                BigInt::zero(),
+                BigInt::zero(),
+                width.clone(),
+            ));
+            ranges.push(LinearRange::new(
+                vec![(0, column.max.clone()), (1 + j, -one())],
+                BigInt::zero(),
+                BigInt::zero(),
+                width,
+            ));
+        }
+        for histogram in histograms.iter() {
+            let column = &source.columns[histogram.column];
+            let (edges, _) = extended_edges(histogram, column);
+            let offset = columns.len();
+            for edges in edges.windows(2) {
+                columns.push(Column::new(
+                    &format!("hist({})[{}, {})", column.name, edges[0], edges[1]),
+                    0,
+                    BigInt::zero(),
+                    one(),
+                ));
+            }
+
+            // A present row sets exactly one bucket, a padding row none.
+            let n_bucket = edges.len() - 1;
+            let mut sum: Vec<_> = (0..n_bucket).map(|k| (offset + k, one())).collect();
+            sum.push((0, -one()));
+            ranges.push(LinearRange::new(
+                sum,
+                BigInt::zero(),
+                BigInt::zero(),
+                BigInt::zero(),
+            ));
+
+            // The set bucket [lo, hi) contains x: x - lo >= 0, hi - 1 - x >= 0.
+            let x = 1 + histogram.column;
+            let width = &edges[n_bucket] - &edges[0];
+            let mut lower: Vec<_> = (0..n_bucket).map(|k| (offset + k, -&edges[k])).collect();
+            lower.push((x, one()));
+            ranges.push(LinearRange::new(
+                lower,
+                BigInt::zero(),
+                BigInt::zero(),
+                width.clone(),
+            ));
+            let mut upper: Vec<_> = (0..n_bucket)
+                .map(|k| (offset + k, edges[k + 1].clone()))
+                .collect();
+            upper.push((0, -one()));
+            upper.push((x, -one()));
+            ranges.push(LinearRange::new(
+                upper,
+                BigInt::zero(),
+                BigInt::zero(),
+                width,
+            ));
+        }
+        let schema = Schema::new(columns)?;
+        schema.check_clients(n_client)?;
+        Ok(Self {
+            source: source.clone(),
+            max_rows,
+            n_client,
+            histograms,
+            schema,
+            squares,
+            ranges,
+        })
+    }
+
+    /// The schema of the clients' datasets before encoding.
+    pub const fn source(&self) -> &Schema {
+        &self.source
+    }
+
+    pub const fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The number of clients the encoder was built for.
+    pub const fn n_client(&self) -> usize {
+        self.n_client
+    }
+
+    /// The largest number of clients whose encoded datasets can be
+    /// aggregated and opened.
+    pub fn max_clients(&self) -> usize {
+        self.schema
+            .columns
+            .iter()
+            .map(|el| {
+                if el.max.is_zero() {
+                    usize::MAX
+                } else {
+                    usize::try_from(BigInt::from(MAX_VALUE) / &el.max).unwrap_or(usize::MAX)
+                }
+            })
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    pub fn encode(&self, dataset: &Dataset) -> anyhow::Result<Dataset> {
+        if dataset.schema != self.source {
+            return Err(anyhow!("Dataset does not match the statistics schema."));
+        }
+        let rows = &dataset.dataset;
+        if rows.len() > self.max_rows {
+            return Err(anyhow!(
+                "Dataset has {} rows, at most {} supported.",
+                rows.len(),
+                self.max_rows
+            ));
+        }
+        let mut encoded: Vec<Vec<BigInt>> = rows
+            .iter()
+            .map(|row| {
+                let mut cells = vec![BigInt::from(1)];
+                cells.extend(row.iter().cloned());
+                cells.extend(row.iter().map(|el| el * el));
+                for histogram in self.histograms.iter() {
+                    let column = &self.source.columns[histogram.column];
+                    let value = &row[histogram.column];
+                    cells.extend(
+                        extended_edges(histogram, column)
+                            .0
+                            .windows(2)
+                            .map(|w| BigInt::from((&w[0] <= value && value < &w[1]) as u8)),
+                    );
+                }
+                cells
+            })
+            .collect();
+        encoded.resize(self.max_rows, vec![BigInt::zero(); self.schema.len()]);
+        Dataset::with_schema(&self.schema, &encoded)
+    }
+
+    /// A client contributing the encoded rows of `client`'s dataset, with
+    /// the same keys, secret and round.
+    pub fn client(&self, client: &Client) -> anyhow::Result<Client> {
+        let mut encoded = Client::new(
+            client.id,
+            client.key_pair.clone(),
+            self.encode(&client.dataset)?,
+            client.secret.clone(),
+        );
+        encoded.round = client.round;
+        Ok(encoded)
+    }
+
+    /// Proves the rows of a client returned by [`StatsEncoder::client`] are
+    /// well formed.
+    pub fn prove(&self, client: &Client) -> anyhow::Result<Vec<StatsProof>> {
+        if client.dataset.schema != self.schema {
+            return Err(anyhow!("Client does not hold encoded rows."));
+        }
+        let randomness = client.randomness();
+        let generators = client.generators();
+        client
+            .dataset
+            .dataset
+            .par_iter()
+            .zip(generators.par_chunks(self.schema.len()))
+            .map(|(row, hs)| StatsProof::prove(&self.squares, &self.ranges, row, &randomness, hs))
+            .collect()
+    }
+
+    /// The upload of a client returned by [`StatsEncoder::client`], with the
+    /// proofs that its rows are well formed.
+    pub fn upload(
+        &self,
+        client: &Client,
+        mkhs: &Mkhs,
+        masking_key: &MaskingKey,
+        peers: &HashMap<u64, G1Projective>,
+        norm_bound: Option<&NormBound>,
+    ) -> anyhow::Result<Upload> {
+        let mut upload = client.upload(mkhs, masking_key, peers, norm_bound)?;
+        upload.stats_proofs = self.prove(client)?;
+        Ok(upload)
+    }
+
+    pub fn submission(
+        &self,
+        client: &Client,
+        mkhs: &Mkhs,
+        masking_key: &MaskingKey,
+        peers: &HashMap<u64, G1Projective>,
+        norm_bound: Option<&NormBound>,
+    ) -> anyhow::Result<Submission> {
+        let upload = self.upload(client, mkhs, masking_key, peers, norm_bound)?;
+        Ok(Submission::sign(&client.key_pair.sk, client.round, upload))
+    }
+
+    /// Checks every committed cell is within its column bounds and every
+    /// committed row is well formed.
+    pub fn verify(
+        &self,
+        commitments: &CommitmentMatrix,
+        randomness_commitment: &Commitment,
+        range_proofs: &[RangeProof],
+        proofs: &[StatsProof],
+    ) -> anyhow::Result<()> {
+        commitments.check_shape(self.max_rows, self.schema.len())?;
+        Aggregator::verify_range_proofs(
+            &self.schema,
+            commitments,
+            randomness_commitment,
+            range_proofs,
+        )?;
+        if proofs.len() != commitments.rows {
+            return Err(anyhow!(
+                "Expected {} statistics proofs, got {}.",
+                commitments.rows,
+                proofs.len()
+            ));
+        }
+        let generators = commitments.generators();
+        proofs
+            .par_iter()
+            .zip(generators.par_chunks(self.schema.len()))
+            .enumerate()
+            .try_for_each(|(i, (proof, hs))| {
+                proof
+                    .verify(
+                        &self.squares,
+                        &self.ranges,
+                        commitments.row(i).unwrap(),
+                        randomness_commitment,
+                        hs,
+                    )
+                    .map_err(|e| anyhow!("Row {}: {}", i, e))
+            })
+    }
+
+    /// Sums an aggregated table of encoded rows into statistics. The result
+    /// should be verified first, e.g. with [`Client::verify_result`].
+    pub fn decode(&self, table: &Table) -> anyhow::Result<Statistics> {
+        if table.schema != self.schema {
+            return Err(anyhow!("Table does not match the statistics schema."));
+        }
+        let totals: Vec<BigInt> = (0..self.schema.len())
+            .map(|i| table.rows.iter().map(|row| &row[i]).sum())
+            .collect();
+        let n_col = self.source.len();
+        let decode = |range: std::ops::Range<usize>| -> Vec<BigDecimal> {
+            range
+                .map(|i| self.schema.columns[i].decode(&totals[i]))
+                .collect()
+        };
+        let mut offset = 1 + 2 * n_col;
+        let histograms = self
+            .histograms
+            .iter()
+            .map(|histogram| {
+                // Skip the buckets below and above the histogram's edges.
+                let column = &self.source.columns[histogram.column];
+                let (edges, start) = extended_edges(histogram, column);
+                let first = offset + start;
+                offset += edges.len() - 1;
+                totals[first..first + histogram.edges.len() - 1].to_vec()
+            })
+            .collect();
+        Ok(Statistics {
+            count: totals[0].clone(),
+            sums: decode(1..1 + n_col),
+            sums_of_squares: decode(1 + n_col..1 + 2 * n_col),
+            histograms,
+        })
+    }
+
+    pub fn statistics(&self, result: &AggregationResult) -> anyhow::Result<Statistics> {
+        self.decode(&result.table)
+    }
+}
+
+/// The histogram's edges, extended with a bucket from the column minimum
+/// and one to past the column maximum when values can fall outside them, so
+/// that every value of the column falls in exactly one bucket. Also returns
+/// the index of the histogram's first bucket.
+fn extended_edges(histogram: &Histogram, column: &Column) -> (Vec<BigInt>, usize) {
+    let (first, last) = (
+        &histogram.edges[0],
+        &histogram.edges[histogram.edges.len() - 1],
+    );
+    let mut edges = vec![];
+    if &column.min < first {
+        edges.push(column.min.clone());
+    }
+    let start = edges.len();
+    edges.extend(histogram.edges.iter().cloned());
+    if &column.max >= last {
+        edges.push(&column.max + 1);
+    }
+    (edges, start)
+}
+
+impl Statistics {
+    pub fn mean(&self, column: usize) -> Option<BigDecimal> {
+        if self.count.is_zero() {
+            return None;
+        }
+        Some(self.sums.get(column)? / BigDecimal::from(self.count.clone()))
+    }
+
+    /// Population variance, `E[x^2] - E[x]^2`.
+    pub fn variance(&self, column: usize) -> Option<BigDecimal> {
+        let mean = self.mean(column)?;
+        let mean_of_squares =
+            self.sums_of_squares.get(column)? / BigDecimal::from(self.count.clone());
+        Some(mean_of_squares - &mean * &mean)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::mkhs::Mkhs;
+    use crate::system::masking::unmask;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    fn encoder(max_rows: usize, edges: &[i64]) -> StatsEncoder {
+        let histogram = Histogram {
+            column: 0,
+            edges: edges.iter().map(|el| BigInt::from(*el)).collect(),
+        };
+        StatsEncoder::new(&Schema::build(1, 1), max_rows, 2, vec![histogram]).unwrap()
+    }
+
+    fn client(encoder: &StatsEncoder, mkhs: &Mkhs, id: u64, values: &[i64]) -> Client {
+        let rows: Vec<Vec<BigInt>> = values.iter().map(|el| vec![BigInt::from(*el)]).collect();
+        let dataset = Dataset::with_schema(&encoder.source, &rows).unwrap();
+        let client = Client::new(id, mkhs.generate_keys(id), dataset, BigInt::from(id * 3));
+        encoder.client(&client).unwrap()
+    }
+
+    fn verify(
+        encoder: &StatsEncoder,
+        client: &Client,
+        proofs: &[StatsProof],
+    ) -> anyhow::Result<()> {
+        encoder.verify(
+            &client.compute_commitments(),
+            &client.commit_randomness(),
+            &client.compute_range_proofs()?,
+            proofs,
+        )
+    }
+
+    #[test]
+    fn test_statistics() {
+        // Every cell of the aggregate is opened by a discrete log, so the
+        // encoding is kept to one row of 5 cells.
+        let encoder = encoder(1, &[0, 5, 11]);
+
+        let mkhs = Mkhs::setup(encoder.schema().len());
+        let clients = [
+            client(&encoder, &mkhs, 1, &[2]),
+            client(&encoder, &mkhs, 2, &[8]),
+        ];
+        for client in clients.iter() {
+            let proofs = encoder.prove(client).unwrap();
+            assert!(verify(&encoder, client, &proofs).is_ok());
+        }
+
+        let randomness = unmask(&clients.iter().map(|el| el.randomness()).collect::<Vec<_>>());
+        let result = Aggregator::aggregate(
+            &mkhs,
+            encoder.schema(),
+            &clients
+                .iter()
+                .map(|el| el.compute_commitments())
+                .collect::<Vec<_>>(),
+            &clients
+                .iter()
+                .map(|el| el.compute_signature(&mkhs, &el.dataset.fr()).unwrap())
+                .collect::<Vec<_>>(),
+            &clients
+                .iter()
+                .map(|el| el.commit_randomness())
+                .collect::<Vec<_>>(),
+            &randomness,
+        )
+        .unwrap();
+        let pks: HashMap<_, _> = clients
+            .iter()
+            .map(|el| (el.id, el.key_pair.pk.clone()))
+            .collect();
+        assert!(Client::verify_result(&mkhs, &pks, &result, &randomness).is_ok());
+
+        // Values 0.2 and 0.8.
+        let stats = encoder.statistics(&result).unwrap();
+        assert_eq!(stats.count, BigInt::from(2));
+        assert_eq!(stats.mean(0).unwrap(), BigDecimal::from_str("0.5").unwrap());
+        assert_eq!(
+            stats.variance(0).unwrap(),
+            BigDecimal::from_str("0.09").unwrap()
+        );
+        assert_eq!(
+            stats.histograms,
+            vec![vec![BigInt::from(1), BigInt::from(1)]]
+        );
+        assert!(stats.mean(1).is_none());
+    }
+
+    #[test]
+    fn test_padded_rows() {
+        // Buckets [0, 1), [1, 5), [5, 10) and [10, 11).
+        let encoder = encoder(3, &[1, 5, 10]);
+        let source = Schema::build(1, 1);
+        let rows = vec![vec![BigInt::from(0)], vec![BigInt::from(10)]];
+        let encoded = encoder
+            .encode(&Dataset::with_schema(&source, &rows).unwrap())
+            .unwrap();
+        assert_eq!(encoded.dataset.len(), 3);
+        assert!(encoded.dataset[2].iter().all(|el| el.is_zero()));
+
+        let table = Table::build(encoder.schema(), &encoded.dataset).unwrap();
+        let stats = encoder.decode(&table).unwrap();
+        assert_eq!(stats.count, BigInt::from(2));
+        assert_eq!(stats.mean(0).unwrap(), BigDecimal::from_str("0.5").unwrap());
+        assert_eq!(
+            stats.histograms,
+            vec![vec![BigInt::from(0), BigInt::from(0)]]
+        );
+    }
+
+    #[test]
+    fn test_forged_rows() {
+        let encoder = encoder(3, &[0, 5, 10]);
+        let mkhs = Mkhs::setup(encoder.schema().len());
+        let honest = client(&encoder, &mkhs, 1, &[2, 7]);
+        let proofs = encoder.prove(&honest).unwrap();
+
+        // Rows are (count, x, x^2, [0, 5), [5, 10), [10, 11)), and every
+        // forgery keeps each cell within its column bounds.
+        let forgeries: [&[(usize, usize, i64)]; 5] = [
+            &[(0, 2, 5)],            // a square that is not the square of x
+            &[(0, 3, 0)],            // no bucket set
+            &[(1, 3, 1), (1, 4, 0)], // 7 counted in the bucket [0, 5)
+            &[(2, 1, 4)],            // a value in a padding row
+            &[(2, 0, 1)],            // a counted row in no bucket
+        ];
+        for cells in forgeries {
+            let mut dataset = honest.dataset.clone();
+            for (row, col, value) in cells {
+                dataset.dataset[*row][*col] = BigInt::from(*value);
+            }
+            let forged = Client::new(
+                honest.id,
+                honest.key_pair.clone(),
+                dataset,
+                honest.secret.clone(),
+            );
+            assert!(verify(&encoder, &forged, &proofs).is_err());
+            if let Ok(proofs) = encoder.prove(&forged) {
+                assert!(verify(&encoder, &forged, &proofs).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_stats_encoder_bounds() {
+        let source = Schema::build(1, 1);
+        let encoder = StatsEncoder::new(&source, 1, 2, vec![]).unwrap();
+        let rows = vec![vec![BigInt::from(1)], vec![BigInt::from(2)]];
+        let encoded = encoder.encode(&Dataset::with_schema(&source, &rows).unwrap());
+        assert!(encoded.is_err());
+
+        let histogram = Histogram {
+            column: 0,
+            edges: vec![BigInt::from(5), BigInt::from(5)],
+        };
+        assert!(StatsEncoder::new(&source, 1, 2, vec![histogram]).is_err());
+        // Squares of values up to 10^5 can be summed for 109 clients, but
+        // not 110.
+        let large = StatsEncoder::new(&Schema::build(1, 5), 1, 109, vec![]).unwrap();
+        assert_eq!(large.max_clients(), 109);
+        assert!(StatsEncoder::new(&Schema::build(1, 5), 1, 110, vec![]).is_err());
+    }
+}
@@ -0,0 +1,1029 @@
+use crate::primitives::commitment::{Commitment, CommitmentMatrix};
+use crate::primitives::consistency_proof::ConsistencyProof;
+use crate::primitives::ec::Point;
+use crate::primitives::mkhs::{ColumnProof, KeyPair, Lam, Mkhs, Signature, PK, SK};
+use crate::primitives::norm_proof::{Norm, NormBound, NormProof, SquareProof};
+use crate::primitives::opening_proof::OpeningProof;
+use crate::primitives::range_proof::{BitDecomposition, BitProof, PedersenRangeProof, RangeProof};
+use crate::primitives::shamir::Share;
+use crate::primitives::stats_proof::{SquareLinkProof, StatsProof};
+use crate::system::aggregator::{AggregationResult, PartialAggregate};
+use crate::system::masking::MaskingKey;
+use crate::system::protocol::Upload;
+use crate::system::registry::{Registration, Revocation};
+use crate::system::session::Submission;
+use crate::system::stats::{Histogram, StatsEncoder};
+use crate::util::dataset::Dataset;
+use crate::util::schema::{Column, Schema, Table};
+use anyhow::anyhow;
+use ark_bls12_381::{Bls12_381, Fr, G1Projective, G2Projective};
+use ark_ec::pairing::PairingOutput;
+use ark_ec::short_weierstrass::{Projective, SWCurveConfig};
+use ark_ec::Group;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use num_bigint::BigInt;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+
+pub trait Encode {
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self>;
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut buf = bytes;
+        let value = Self::decode(&mut buf)?;
+        if !buf.is_empty() {
+            return Err(anyhow!("{} trailing bytes.", buf.len()));
+        }
+        Ok(value)
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(anyhow!("Unexpected end of input."));
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+impl Encode for u8 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self);
+    }
+}
+
+impl Decode for u8 {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(take(buf, 1)?[0])
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Decode for u32 {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::from_le_bytes(take(buf, 4)?.try_into()?))
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Decode for u64 {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::from_le_bytes(take(buf, 8)?.try_into()?))
+    }
+}
+
+impl Encode for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf);
+    }
+}
+
+impl Decode for usize {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(u64::decode(buf)?.try_into()?)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        u8::from(*self).encode(buf);
+    }
+}
+
+impl Decode for bool {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(anyhow!("Invalid bool {}.", v)),
+        }
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        buf.extend_from_slice(self);
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode(buf);
+    }
+}
+
+impl Decode for String {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let len = u32::decode(buf)? as usize;
+        Ok(Self::from_utf8(take(buf, len)?.to_vec())?)
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        self.iter().for_each(|el| el.encode(buf));
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let len = u32::decode(buf)? as usize;
+        let mut res = Self::with_capacity(len.min(buf.len()));
+        for _ in 0..len {
+            res.push(T::decode(buf)?);
+        }
+        Ok(res)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.is_some().encode(buf);
+        if let Some(value) = self {
+            value.encode(buf);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(if bool::decode(buf)? {
+            Some(T::decode(buf)?)
+        } else {
+            None
+        })
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok((A::decode(buf)?, B::decode(buf)?))
+    }
+}
+
+impl<K: Encode + Ord, V: Encode> Encode for HashMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        (entries.len() as u32).encode(buf);
+        entries.iter().for_each(|(k, v)| {
+            k.encode(buf);
+            v.encode(buf);
+        });
+    }
+}
+
+impl<K: Decode + Eq + Hash, V: Decode> Decode for HashMap<K, V> {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Vec::<(K, V)>::decode(buf)?.into_iter().collect())
+    }
+}
+
+impl Encode for BigInt {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_signed_bytes_le().as_slice().encode(buf);
+    }
+}
+
+impl Decode for BigInt {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let len = u32::decode(buf)? as usize;
+        Ok(Self::from_signed_bytes_le(take(buf, len)?))
+    }
+}
+
+macro_rules! impl_ark_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    self.serialize_compressed(buf).unwrap();
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+                    <$t>::deserialize_compressed(buf)
+                        .map_err(|e| anyhow!("Invalid group element: {}.", e))
+                }
+            }
+        )*
+    };
+}
+
+impl_ark_codec!(Fr, PairingOutput<Bls12_381>);
+
+impl<P: SWCurveConfig> Encode for Projective<P> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.serialize_compressed(buf).unwrap();
+    }
+}
+
+impl<P: SWCurveConfig> Decode for Projective<P> {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Self::deserialize_compressed(buf).map_err(|e| anyhow!("Invalid group element: {}.", e))
+    }
+}
+
+impl Encode for ed25519_dalek::Signature {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_bytes());
+    }
+}
+
+impl Decode for ed25519_dalek::Signature {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::from_bytes(take(buf, 64)?.try_into()?))
+    }
+}
+
+impl Encode for VerifyingKey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for VerifyingKey {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Self::from_bytes(take(buf, 32)?.try_into()?).map_err(|_| anyhow!("Invalid verifying key."))
+    }
+}
+
+impl Encode for SigningKey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for SigningKey {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::from_bytes(take(buf, 32)?.try_into()?))
+    }
+}
+
+impl Encode for Point {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+}
+
+impl Decode for Point {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::from(G1Projective::decode(buf)?))
+    }
+}
+
+impl Encode for Commitment {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.c.encode(buf);
+    }
+}
+
+impl Decode for Commitment {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::new(Point::decode(buf)?))
+    }
+}
+
+impl Encode for CommitmentMatrix {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.rows.encode(buf);
+        self.cols.encode(buf);
+        self.data.encode(buf);
+    }
+}
+
+impl Decode for CommitmentMatrix {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Self::new(usize::decode(buf)?, usize::decode(buf)?, Vec::decode(buf)?)
+    }
+}
+
+impl Encode for Lam {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.client_id.encode(buf);
+        self.sig.encode(buf);
+        self.big_z.encode(buf);
+        self.big_a.encode(buf);
+        self.big_c.encode(buf);
+        self.count.encode(buf);
+    }
+}
+
+impl Decode for Lam {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let lam = Self {
+            client_id: u64::decode(buf)?,
+            sig: ed25519_dalek::Signature::decode(buf)?,
+            big_z: G2Projective::decode(buf)?,
+            big_a: G1Projective::decode(buf)?,
+            big_c: G1Projective::decode(buf)?,
+            count: u64::decode(buf)?,
+        };
+        if lam.count == 0 {
+            return Err(anyhow!("Lam merges no signatures."));
+        }
+        Ok(lam)
+    }
+}
+
+impl Encode for Signature {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.lams.encode(buf);
+        self.big_r.encode(buf);
+        self.big_s.encode(buf);
+    }
+}
+
+impl Decode for Signature {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            lams: Vec::decode(buf)?,
+            big_r: G1Projective::decode(buf)?,
+            big_s: G2Projective::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for ColumnProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.rest.encode(buf);
+        self.t.encode(buf);
+        self.z.encode(buf);
+    }
+}
+
+impl Decode for ColumnProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            rest: G1Projective::decode(buf)?,
+            t: G1Projective::decode(buf)?,
+            z: Vec::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for SK {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.k.encode(buf);
+        self.sk_sig.encode(buf);
+        self.x.encode(buf);
+        self.y.encode(buf);
+    }
+}
+
+impl Decode for SK {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            k: u64::decode(buf)?,
+            sk_sig: SigningKey::decode(buf)?,
+            x: Fr::decode(buf)?,
+            y: Fr::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for PK {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.pk_sig.encode(buf);
+        self.h.encode(buf);
+        self.big_y.encode(buf);
+    }
+}
+
+impl Decode for PK {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            pk_sig: VerifyingKey::decode(buf)?,
+            h: PairingOutput::decode(buf)?,
+            big_y: G2Projective::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for KeyPair {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.sk.encode(buf);
+        self.pk.encode(buf);
+    }
+}
+
+impl Decode for KeyPair {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            sk: SK::decode(buf)?,
+            pk: PK::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Mkhs {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.g1.encode(buf);
+        self.g2.encode(buf);
+        self.gt.encode(buf);
+        self.t.encode(buf);
+        self.big_hs.encode(buf);
+    }
+}
+
+impl Decode for Mkhs {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let mkhs = Self {
+            g1: G1Projective::decode(buf)?,
+            g2: G2Projective::decode(buf)?,
+            gt: PairingOutput::decode(buf)?,
+            t: usize::decode(buf)?,
+            big_hs: Vec::decode(buf)?,
+        };
+        if mkhs.big_hs.len() != mkhs.t {
+            return Err(anyhow!("Invalid MKHS parameters."));
+        }
+        Ok(mkhs)
+    }
+}
+
+impl Encode for MaskingKey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.secret.encode(buf);
+    }
+}
+
+impl Decode for MaskingKey {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let secret = Fr::decode(buf)?;
+        Ok(Self {
+            secret,
+            public: G1Projective::generator() * secret,
+        })
+    }
+}
+
+impl Encode for Registration {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.client_id.encode(buf);
+        self.pk.encode(buf);
+        self.valid_from.encode(buf);
+        self.sig.encode(buf);
+    }
+}
+
+impl Decode for Registration {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            client_id: u64::decode(buf)?,
+            pk: PK::decode(buf)?,
+            valid_from: u64::decode(buf)?,
+            sig: ed25519_dalek::Signature::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Revocation {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.client_id.encode(buf);
+        self.from.encode(buf);
+        self.sig.encode(buf);
+    }
+}
+
+impl Decode for Revocation {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            client_id: u64::decode(buf)?,
+            from: u64::decode(buf)?,
+            sig: ed25519_dalek::Signature::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Share {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.x.encode(buf);
+        self.y.encode(buf);
+    }
+}
+
+impl Decode for Share {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            x: u64::decode(buf)?,
+            y: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for BitProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.commitment.encode(buf);
+        self.e0.encode(buf);
+        self.e1.encode(buf);
+        self.s0.encode(buf);
+        self.s1.encode(buf);
+    }
+}
+
+impl Decode for BitProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            commitment: Point::decode(buf)?,
+            e0: BigInt::decode(buf)?,
+            e1: BigInt::decode(buf)?,
+            s0: BigInt::decode(buf)?,
+            s1: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for BitDecomposition {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.lower.encode(buf);
+        self.upper.encode(buf);
+        self.sum_e.encode(buf);
+        self.sum_z.encode(buf);
+    }
+}
+
+impl Decode for BitDecomposition {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            lower: Vec::decode(buf)?,
+            upper: Vec::decode(buf)?,
+            sum_e: BigInt::decode(buf)?,
+            sum_z: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for RangeProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.bits.encode(buf);
+        self.link_e.encode(buf);
+        self.link_r.encode(buf);
+        self.link_rho.encode(buf);
+    }
+}
+
+impl Decode for RangeProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            bits: BitDecomposition::decode(buf)?,
+            link_e: BigInt::decode(buf)?,
+            link_r: BigInt::decode(buf)?,
+            link_rho: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for PedersenRangeProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.bits.encode(buf);
+        self.link_e.encode(buf);
+        self.link_z.encode(buf);
+    }
+}
+
+impl Decode for PedersenRangeProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            bits: BitDecomposition::decode(buf)?,
+            link_e: BigInt::decode(buf)?,
+            link_z: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for SquareProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.big_d.encode(buf);
+        self.big_q.encode(buf);
+        self.e.encode(buf);
+        self.z_d.encode(buf);
+        self.z_delta.encode(buf);
+        self.z_tau.encode(buf);
+        self.z_r.encode(buf);
+    }
+}
+
+impl Decode for SquareProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            big_d: Point::decode(buf)?,
+            big_q: Point::decode(buf)?,
+            e: BigInt::decode(buf)?,
+            z_d: BigInt::decode(buf)?,
+            z_delta: BigInt::decode(buf)?,
+            z_tau: BigInt::decode(buf)?,
+            z_r: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for NormProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.cells.encode(buf);
+        self.squares.encode(buf);
+        self.sum.encode(buf);
+    }
+}
+
+impl Decode for NormProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            cells: Vec::decode(buf)?,
+            squares: Vec::decode(buf)?,
+            sum: Option::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for SquareLinkProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.square.encode(buf);
+        self.e.encode(buf);
+        self.z_sigma.encode(buf);
+        self.z_r.encode(buf);
+    }
+}
+
+impl Decode for SquareLinkProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            square: SquareProof::decode(buf)?,
+            e: BigInt::decode(buf)?,
+            z_sigma: BigInt::decode(buf)?,
+            z_r: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for StatsProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.squares.encode(buf);
+        self.ranges.encode(buf);
+    }
+}
+
+impl Decode for StatsProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            squares: Vec::decode(buf)?,
+            ranges: Vec::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Histogram {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.column.encode(buf);
+        self.edges.encode(buf);
+    }
+}
+
+impl Decode for Histogram {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            column: usize::decode(buf)?,
+            edges: Vec::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for StatsEncoder {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.source.encode(buf);
+        self.max_rows.encode(buf);
+        self.n_client.encode(buf);
+        self.histograms.encode(buf);
+    }
+}
+
+impl Decode for StatsEncoder {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Self::new(
+            &Schema::decode(buf)?,
+            usize::decode(buf)?,
+            usize::decode(buf)?,
+            Vec::decode(buf)?,
+        )
+    }
+}
+
+impl Encode for NormBound {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self.norm {
+            Norm::LInf => 0u8.encode(buf),
+            Norm::L2 => 1u8.encode(buf),
+        }
+        self.center.encode(buf);
+        self.bound.encode(buf);
+    }
+}
+
+impl Decode for NormBound {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let norm = match u8::decode(buf)? {
+            0 => Norm::LInf,
+            1 => Norm::L2,
+            tag => return Err(anyhow!("Unknown norm {}.", tag)),
+        };
+        Ok(Self::new(norm, BigInt::decode(buf)?, BigInt::decode(buf)?))
+    }
+}
+
+impl Encode for ConsistencyProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.e.encode(buf);
+        self.z_s.encode(buf);
+        self.z_w.encode(buf);
+        self.z_r.encode(buf);
+    }
+}
+
+impl Decode for ConsistencyProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            e: BigInt::decode(buf)?,
+            z_s: Fr::decode(buf)?,
+            z_w: Vec::decode(buf)?,
+            z_r: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for OpeningProof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.e.encode(buf);
+        self.z.encode(buf);
+    }
+}
+
+impl Decode for OpeningProof {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            e: BigInt::decode(buf)?,
+            z: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Column {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.name.encode(buf);
+        self.decimals.encode(buf);
+        self.min.encode(buf);
+        self.max.encode(buf);
+    }
+}
+
+impl Decode for Column {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: String::decode(buf)?,
+            decimals: u32::decode(buf)?,
+            min: BigInt::decode(buf)?,
+            max: BigInt::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Schema {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.columns.encode(buf);
+    }
+}
+
+impl Decode for Schema {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Self::new(Vec::decode(buf)?)
+    }
+}
+
+impl Encode for Table {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.schema.encode(buf);
+        self.rows.encode(buf);
+    }
+}
+
+impl Decode for Table {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let schema = Schema::decode(buf)?;
+        Self::build(&schema, &Vec::decode(buf)?)
+    }
+}
+
+impl Encode for Dataset {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.schema.encode(buf);
+        self.dataset.encode(buf);
+    }
+}
+
+impl Decode for Dataset {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let schema = Schema::decode(buf)?;
+        Self::with_schema(&schema, &Vec::decode(buf)?)
+    }
+}
+
+impl Encode for AggregationResult {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.commitments.encode(buf);
+        self.randomness_commitment.encode(buf);
+        self.signature.encode(buf);
+        self.table.encode(buf);
+        self.opening_proofs.encode(buf);
+    }
+}
+
+impl Decode for AggregationResult {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            commitments: CommitmentMatrix::decode(buf)?,
+            randomness_commitment: Commitment::decode(buf)?,
+            signature: Signature::decode(buf)?,
+            table: <Table as Decode>::decode(buf)?,
+            opening_proofs: Vec::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Submission {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.client_id.encode(buf);
+        self.round.encode(buf);
+        self.upload.encode(buf);
+        self.tag.encode(buf);
+    }
+}
+
+impl Decode for Submission {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            client_id: u64::decode(buf)?,
+            round: u64::decode(buf)?,
+            upload: Upload::decode(buf)?,
+            tag: ed25519_dalek::Signature::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for PartialAggregate {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.clients
+            .iter()
+            .copied()
+            .collect::<Vec<u64>>()
+            .encode(buf);
+        self.commitments.encode(buf);
+        self.randomness_commitment.encode(buf);
+        self.signature.encode(buf);
+        self.masked_randomness.encode(buf);
+    }
+}
+
+impl Decode for PartialAggregate {
+    fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            clients: Vec::<u64>::decode(buf)?
+                .into_iter()
+                .collect::<BTreeSet<_>>(),
+            commitments: CommitmentMatrix::decode(buf)?,
+            randomness_commitment: Commitment::decode(buf)?,
+            signature: Signature::decode(buf)?,
+            masked_randomness: BigInt::decode(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mkhs = Mkhs::setup(2);
+        let key_pair = mkhs.generate_keys(7);
+        let messages = vec![Fr::from(3), Fr::from(4)];
+        let signature = mkhs.sign(&key_pair.sk, &messages).unwrap();
+
+        assert_eq!(Mkhs::from_bytes(&mkhs.to_bytes()).unwrap(), mkhs);
+        assert_eq!(KeyPair::from_bytes(&key_pair.to_bytes()).unwrap(), key_pair);
+        let masking_key = MaskingKey::generate();
+        assert_eq!(
+            MaskingKey::from_bytes(&masking_key.to_bytes()).unwrap(),
+            masking_key
+        );
+        assert_eq!(
+            Signature::from_bytes(&signature.to_bytes()).unwrap(),
+            signature
+        );
+
+        let values = vec![BigInt::from(-5), BigInt::from(0), BigInt::from(1) << 100];
+        assert_eq!(
+            Vec::<BigInt>::from_bytes(&values.to_bytes()).unwrap(),
+            values
+        );
+
+        let commitments = CommitmentMatrix::new(
+            1,
+            2,
+            vec![
+                Commitment::default(),
+                Commitment::commit_randomness(&BigInt::from(3)),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            CommitmentMatrix::from_bytes(&commitments.to_bytes()).unwrap(),
+            commitments
+        );
+
+        let dataset = Dataset::build(2, 3, 2);
+        assert_eq!(Dataset::from_bytes(&dataset.to_bytes()).unwrap(), dataset);
+
+        let proof = RangeProof::prove(
+            &BigInt::from(3),
+            &BigInt::from(8),
+            &Point::h(),
+            &BigInt::from(0),
+            &BigInt::from(4),
+        )
+        .unwrap();
+        assert_eq!(RangeProof::from_bytes(&proof.to_bytes()).unwrap(), proof);
+
+        let bound = NormBound::new(Norm::L2, BigInt::from(-3), BigInt::from(40));
+        assert_eq!(NormBound::from_bytes(&bound.to_bytes()).unwrap(), bound);
+
+        let histogram = Histogram {
+            column: 1,
+            edges: vec![BigInt::from(0), BigInt::from(50)],
+        };
+        let encoder = StatsEncoder::new(&Schema::build(2, 2), 3, 4, vec![histogram]).unwrap();
+        assert_eq!(
+            StatsEncoder::from_bytes(&encoder.to_bytes()).unwrap(),
+            encoder
+        );
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let bytes = Mkhs::setup(2).to_bytes();
+        assert!(Mkhs::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut bytes = BigInt::from(5).to_bytes();
+        bytes.push(0);
+        assert!(BigInt::from_bytes(&bytes).is_err());
+
+        let bytes = Point::default().to_bytes();
+        assert!(Point::from_bytes(&bytes[1..]).is_err());
+        // A compressed x coordinate above the field modulus.
+        let mut bytes = vec![0xff; bytes.len()];
+        bytes[0] = 0x9f;
+        assert!(Point::from_bytes(&bytes).is_err());
+
+        let mkhs = Mkhs::setup(2);
+        let key_pair = mkhs.generate_keys(1);
+        let mut signature = mkhs
+            .sign(&key_pair.sk, &[Fr::from(3), Fr::from(4)])
+            .unwrap();
+        signature.lams[0].count = 0;
+        assert!(Signature::from_bytes(&signature.to_bytes()).is_err());
+
+        let mut bytes = NormBound::new(Norm::LInf, BigInt::from(0), BigInt::from(1)).to_bytes();
+        bytes[0] = 2;
+        assert!(NormBound::from_bytes(&bytes).is_err());
+    }
+}
@@ -1,3 +1,6 @@
+use crate::primitives::dp::Mechanism;
+use crate::util::schema::Schema;
+use anyhow::anyhow;
 use ark_bls12_381::Fr;
 use bigdecimal::BigDecimal;
 use num_bigint::{BigInt, ToBigInt};
@@ -8,53 +11,87 @@ use rayon::prelude::*;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Dataset {
+    pub schema: Schema,
     pub dataset: Vec<Vec<BigInt>>,
 }
 
 impl Dataset {
     pub fn new(data: &[Vec<BigInt>]) -> Self {
+        let n_col = data.first().map_or(0, |row| row.len());
         Self {
+            schema: Schema::unbounded(n_col),
             dataset: data.to_vec(),
         }
     }
 
+    pub fn with_schema(schema: &Schema, data: &[Vec<BigInt>]) -> anyhow::Result<Self> {
+        schema.check(data)?;
+        Ok(Self {
+            schema: schema.clone(),
+            dataset: data.to_vec(),
+        })
+    }
+
     pub fn build(n_col: usize, n_row: usize, decimals: u32) -> Self {
-        let values: Vec<f64> = (0..n_row * n_col)
+        Self::sample(&Schema::build(n_col, decimals), n_row)
+    }
+
+    pub fn sample(schema: &Schema, n_row: usize) -> Self {
+        let values: Vec<f64> = (0..n_row * schema.len())
             .map(|_| thread_rng().gen_range(0.0..1.0))
             .collect();
-        let scaling_factor = BigDecimal::from_i64(10_i64.pow(decimals)).unwrap();
-        let res: Vec<Vec<BigInt>> = (0..n_row)
-            .map(|_| sample_values(&values, n_col, decimals, &scaling_factor))
+        let res: Vec<Vec<BigInt>> = (0..n_row).map(|_| sample_values(&values, schema)).collect();
+        Self {
+            schema: schema.clone(),
+            dataset: res,
+        }
+    }
+
+    /// Adds `mechanism` noise to every encoded value before it is committed.
+    /// Noisy values are not clamped, which would bias the aggregate and void
+    /// the privacy accounting: the column bounds must leave
+    /// [`Mechanism::margin`] of headroom around the data, and noise that still
+    /// leaves them is an error.
+    pub fn add_noise(&mut self, mechanism: &Mechanism) -> anyhow::Result<()> {
+        mechanism.check()?;
+        let noisy: Vec<Vec<BigInt>> = self
+            .dataset
+            .par_iter()
+            .map(|row| row.iter().map(|el| el + mechanism.sample()).collect())
             .collect();
-        Self::new(&res)
+        self.schema
+            .check(&noisy)
+            .map_err(|e| anyhow!("Noise exceeds the column bounds: {}", e))?;
+        self.dataset = noisy;
+        Ok(())
     }
 
     pub fn fr(&self) -> Vec<Vec<Fr>> {
-        self.dataset
-            .par_iter()
-            .map(|row| {
-                row.par_iter()
-                    .map(|el| Fr::from(el.to_u64().unwrap()))
-                    .collect()
-            })
-            .collect()
+        to_fr(&self.dataset)
     }
 }
 
-fn sample_values(
-    values: &[f64],
-    n_col: usize,
-    decimals: u32,
-    scaling_factor: &BigDecimal,
-) -> Vec<BigInt> {
+pub fn to_fr(data: &[Vec<BigInt>]) -> Vec<Vec<Fr>> {
+    data.par_iter()
+        .map(|row| {
+            row.par_iter()
+                .map(|el| Fr::from(el.to_u64().unwrap()))
+                .collect()
+        })
+        .collect()
+}
+
+fn sample_values(values: &[f64], schema: &Schema) -> Vec<BigInt> {
     values
         .iter()
-        .choose_multiple(&mut thread_rng(), n_col)
+        .choose_multiple(&mut thread_rng(), schema.len())
         .par_iter()
-        .map(|el| {
+        .zip(schema.columns.par_iter())
+        .map(|(el, column)| {
+            let scaling_factor = BigDecimal::from_i64(10_i64.pow(column.decimals)).unwrap();
             let value = BigDecimal::from_f64(**el)
                 .unwrap()
-                .with_scale(decimals.into());
+                .with_scale(column.decimals.into());
             (value * scaling_factor).to_bigint().unwrap()
         })
         .collect()
@@ -63,9 +100,10 @@ fn sample_values(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::schema::Column;
     use ark_bls12_381::Fr;
     use ark_ff::{BigInteger, PrimeField};
-    use num_traits::ToPrimitive;
+    use num_traits::{ToPrimitive, Zero};
 
     #[test]
     fn test_sample_values() {
@@ -80,4 +118,45 @@ mod tests {
         let temp = BigInt::from_signed_bytes_le(temp.as_slice());
         println!("{}", temp);
     }
+
+    #[test]
+    fn test_with_schema() {
+        let schema = Schema::new(vec![
+            Column::new("a", 0, BigInt::zero(), BigInt::from(10)),
+            Column::new("b", 2, BigInt::zero(), BigInt::from(100)),
+        ])
+        .unwrap();
+
+        let data = vec![vec![BigInt::from(1), BigInt::from(100)]];
+        assert!(Dataset::with_schema(&schema, &data).is_ok());
+
+        let data = vec![vec![BigInt::from(11), BigInt::from(100)]];
+        assert!(Dataset::with_schema(&schema, &data).is_err());
+
+        let data = vec![vec![BigInt::from(1)]];
+        assert!(Dataset::with_schema(&schema, &data).is_err());
+
+        let dataset = Dataset::sample(&schema, 5);
+        assert!(schema.check(&dataset.dataset).is_ok());
+    }
+
+    #[test]
+    fn test_add_noise() {
+        let mechanism = Mechanism::DiscreteGaussian { sigma: 5.0 };
+        let margin = BigInt::from(mechanism.margin());
+        let schema = Schema::new(vec![Column::new("a", 0, BigInt::zero(), &margin * 2)]).unwrap();
+        let data: Vec<Vec<BigInt>> = (0..50).map(|_| vec![margin.clone()]).collect();
+        let mut dataset = Dataset::with_schema(&schema, &data).unwrap();
+
+        dataset.add_noise(&mechanism).unwrap();
+        assert!(schema.check(&dataset.dataset).is_ok());
+        assert_ne!(dataset.dataset, data);
+        assert!(dataset.add_noise(&Mechanism::Skellam { mu: 0.0 }).is_err());
+
+        // Noise is not clamped: without headroom it is rejected.
+        let data: Vec<Vec<BigInt>> = (0..50).map(|_| vec![BigInt::zero()]).collect();
+        let mut dataset = Dataset::with_schema(&schema, &data).unwrap();
+        assert!(dataset.add_noise(&mechanism).is_err());
+        assert_eq!(dataset.dataset, data);
+    }
 }
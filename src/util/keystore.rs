@@ -0,0 +1,270 @@
+use crate::primitives::ec::random_scalar;
+use crate::primitives::mkhs::{KeyPair, Mkhs, Signature, PK, SK};
+use crate::system::masking::MaskingKey;
+use crate::util::codec::{Decode, Encode};
+use anyhow::anyhow;
+use argon2::Argon2;
+use ark_bls12_381::Fr;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use num_bigint::BigInt;
+use rand::{thread_rng, RngCore};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Everything a client keeps secret: its MKHS key pair, the seed of its
+/// commitment randomness and its masking key.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ClientKeys {
+    pub key_pair: KeyPair,
+    pub secret: BigInt,
+    pub masking_key: MaskingKey,
+}
+
+impl ClientKeys {
+    pub fn generate(mkhs: &Mkhs, id: u64) -> Self {
+        Self {
+            key_pair: mkhs.generate_keys(id),
+            secret: random_scalar(),
+            masking_key: MaskingKey::generate(),
+        }
+    }
+}
+
+/// Directory of client keys, one pair of files per client and key version:
+/// `<id>.<version>.sk` holds the `SK`, the commitment secret and the masking
+/// key encrypted under a passphrase, and `<id>.<version>.pk` the matching
+/// `PK` in the clear, to be shared.
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    dir: PathBuf,
+}
+
+impl Keystore {
+    pub fn open(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn save(&self, keys: &ClientKeys, version: u32, passphrase: &str) -> anyhow::Result<()> {
+        let id = keys.key_pair.sk.k;
+        let secrets = (
+            keys.key_pair.sk.clone(),
+            (keys.secret.clone(), keys.masking_key.clone()),
+        );
+        let sk = encrypt(&secrets.to_bytes(), passphrase, &context(id, version))?;
+        fs::write(self.path(id, version, "sk"), sk)?;
+        self.export(id, version, &keys.key_pair.pk)
+    }
+
+    /// Writes a public key, e.g. one received from another client.
+    pub fn export(&self, id: u64, version: u32, pk: &PK) -> anyhow::Result<()> {
+        Ok(fs::write(self.path(id, version, "pk"), pk.to_bytes())?)
+    }
+
+    /// Loads a client's keys, checking the exported `PK` is the one derived
+    /// from the `SK` under `mkhs`.
+    pub fn load(
+        &self,
+        mkhs: &Mkhs,
+        id: u64,
+        version: u32,
+        passphrase: &str,
+    ) -> anyhow::Result<ClientKeys> {
+        let sk = fs::read(self.path(id, version, "sk"))?;
+        let (sk, (secret, masking_key)) = <(SK, (BigInt, MaskingKey))>::from_bytes(&decrypt(
+            &sk,
+            passphrase,
+            &context(id, version),
+        )?)?;
+        if sk.k != id {
+            return Err(anyhow!("Key file belongs to client {}, not {}.", sk.k, id));
+        }
+        let pk = self.load_pk(id, version)?;
+        if pk != mkhs.public_key(&sk) {
+            return Err(anyhow!(
+                "Public key of client {} version {} does not match its secret key.",
+                id,
+                version
+            ));
+        }
+        Ok(ClientKeys {
+            key_pair: KeyPair { sk, pk },
+            secret,
+            masking_key,
+        })
+    }
+
+    pub fn load_pk(&self, id: u64, version: u32) -> anyhow::Result<PK> {
+        PK::from_bytes(&fs::read(self.path(id, version, "pk"))?)
+    }
+
+    /// Generates and saves the next key version of client `id`.
+    pub fn rotate(
+        &self,
+        mkhs: &Mkhs,
+        id: u64,
+        passphrase: &str,
+    ) -> anyhow::Result<(u32, ClientKeys)> {
+        let version = self.versions()?.get(&id).map_or(0, |v| v + 1);
+        let keys = ClientKeys::generate(mkhs, id);
+        self.save(&keys, version, passphrase)?;
+        Ok((version, keys))
+    }
+
+    /// The latest public key version of every client in the keystore.
+    pub fn versions(&self) -> anyhow::Result<HashMap<u64, u32>> {
+        let mut versions = HashMap::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let parts: Vec<&str> = name.to_str().unwrap_or_default().split('.').collect();
+            if let [id, version, "pk"] = parts.as_slice() {
+                if let (Ok(id), Ok(version)) = (id.parse::<u64>(), version.parse::<u32>()) {
+                    let latest = versions.entry(id).or_insert(version);
+                    *latest = (*latest).max(version);
+                }
+            }
+        }
+        Ok(versions)
+    }
+
+    /// The public keys to pass to `Mkhs::verify` for signatures made with
+    /// the given key version of each client.
+    pub fn public_keys(&self, versions: &HashMap<u64, u32>) -> anyhow::Result<HashMap<u64, PK>> {
+        versions
+            .iter()
+            .map(|(id, version)| {
+                let pk = self
+                    .load_pk(*id, *version)
+                    .map_err(|e| anyhow!("Client {} key version {}: {}", id, version, e))?;
+                Ok((*id, pk))
+            })
+            .collect()
+    }
+
+    /// Verifies `signature` with the key version each client signed with,
+    /// given by `versions`, e.g. the mapping returned by
+    /// [`Keystore::versions`] when every client signed with its latest key.
+    pub fn verify(
+        &self,
+        mkhs: &Mkhs,
+        versions: &HashMap<u64, u32>,
+        messages: &[Fr],
+        signature: &Signature,
+    ) -> anyhow::Result<()> {
+        mkhs.verify(&self.public_keys(versions)?, messages, signature)
+    }
+
+    fn path(&self, id: u64, version: u32, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}.{}", id, version, extension))
+    }
+}
+
+/// Encrypts with ChaCha20-Poly1305 under a key derived from `passphrase`
+/// with Argon2. The output is `salt || nonce || ciphertext`, and `context`
+/// is authenticated but not stored.
+pub fn encrypt(plaintext: &[u8], passphrase: &str, context: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    thread_rng().fill_bytes(&mut salt);
+    thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: context,
+            },
+        )
+        .map_err(|_| anyhow!("Encryption failed."))?;
+    Ok([&salt[..], &nonce, &ciphertext].concat())
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str, context: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(anyhow!("Encrypted data is too short."));
+    }
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    cipher(passphrase, salt)?
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: context,
+            },
+        )
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted key file."))
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> anyhow::Result<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}.", e))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn context(id: u64, version: u32) -> Vec<u8> {
+    [
+        &b"viper-keystore"[..],
+        &id.to_le_bytes(),
+        &version.to_le_bytes(),
+    ]
+    .concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keystore_rotation() {
+        let dir = std::env::temp_dir().join(format!("viper-keystore-{}", thread_rng().next_u64()));
+        let keystore = Keystore::open(&dir).unwrap();
+        let mkhs = Mkhs::setup(2);
+        let messages = vec![Fr::from(3), Fr::from(4)];
+
+        let (v0, old) = keystore.rotate(&mkhs, 1, "secret").unwrap();
+        let (v1, new) = keystore.rotate(&mkhs, 1, "secret").unwrap();
+        assert_eq!((v0, v1), (0, 1));
+        assert_eq!(keystore.versions().unwrap(), HashMap::from([(1, 1)]));
+        assert_eq!(keystore.load(&mkhs, 1, 0, "secret").unwrap(), old);
+        assert!(keystore.load(&mkhs, 1, 1, "wrong").is_err());
+
+        // Signatures verify under the key version they were made with.
+        let signature = mkhs
+            .sign(
+                &keystore.load(&mkhs, 1, 1, "secret").unwrap().key_pair.sk,
+                &messages,
+            )
+            .unwrap();
+        let current = keystore.versions().unwrap();
+        assert!(keystore
+            .verify(&mkhs, &current, &messages, &signature)
+            .is_ok());
+        let previous = HashMap::from([(1, 0)]);
+        assert!(keystore
+            .verify(&mkhs, &previous, &messages, &signature)
+            .is_err());
+        assert_eq!(keystore.public_keys(&current).unwrap()[&1], new.key_pair.pk);
+
+        // An exported public key must match the secret key.
+        keystore.export(1, 1, &old.key_pair.pk).unwrap();
+        assert!(keystore.load(&mkhs, 1, 1, "secret").is_err());
+        keystore.export(1, 1, &new.key_pair.pk).unwrap();
+        assert!(keystore.load(&mkhs, 1, 1, "secret").is_ok());
+
+        // A key file cannot be swapped in for another version.
+        fs::copy(dir.join("1.0.sk"), dir.join("1.1.sk")).unwrap();
+        assert!(keystore.load(&mkhs, 1, 1, "secret").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
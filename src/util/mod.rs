@@ -1 +1,4 @@
+pub mod codec;
 pub mod dataset;
+pub mod keystore;
+pub mod schema;
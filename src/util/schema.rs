@@ -0,0 +1,266 @@
+use crate::primitives::dlog::MAX_VALUE;
+use crate::util::codec::Encode;
+use crate::util::dataset::to_fr;
+use anyhow::anyhow;
+use ark_bls12_381::Fr;
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub decimals: u32,
+    pub min: BigInt,
+    pub max: BigInt,
+}
+
+impl Column {
+    pub fn new(name: &str, decimals: u32, min: BigInt, max: BigInt) -> Self {
+        Self {
+            name: name.to_string(),
+            decimals,
+            min,
+            max,
+        }
+    }
+
+    pub fn scale(&self) -> BigInt {
+        BigInt::from(10).pow(self.decimals)
+    }
+
+    pub fn encode(&self, value: &BigDecimal) -> anyhow::Result<BigInt> {
+        let (encoded, _) = value
+            .with_scale(self.decimals.into())
+            .into_bigint_and_exponent();
+        self.check(&encoded)?;
+        Ok(encoded)
+    }
+
+    pub fn decode(&self, value: &BigInt) -> BigDecimal {
+        BigDecimal::new(value.clone(), self.decimals.into())
+    }
+
+    pub fn check(&self, value: &BigInt) -> anyhow::Result<()> {
+        if value < &self.min || value > &self.max {
+            return Err(anyhow!(
+                "Value {} out of bounds [{}, {}] for column {}.",
+                value,
+                self.min,
+                self.max,
+                self.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Schema {
+    pub columns: Vec<Column>,
+}
+
+impl Schema {
+    pub fn new(columns: Vec<Column>) -> anyhow::Result<Self> {
+        for (i, column) in columns.iter().enumerate() {
+            if columns[..i].iter().any(|el| el.name == column.name) {
+                return Err(anyhow!("Duplicated column {}.", column.name));
+            }
+            if column.min > column.max || column.min < BigInt::zero() {
+                return Err(anyhow!("Invalid bounds for column {}.", column.name));
+            }
+        }
+        Ok(Self { columns })
+    }
+
+    pub fn build(n_col: usize, decimals: u32) -> Self {
+        let max = BigInt::from(10).pow(decimals);
+        let columns = (0..n_col)
+            .map(|i| Column::new(&format!("col_{}", i), decimals, BigInt::zero(), max.clone()))
+            .collect();
+        Self { columns }
+    }
+
+    /// Integer columns taking any 32-bit value.
+    pub fn unbounded(n_col: usize) -> Self {
+        let max = BigInt::from(u32::MAX);
+        let columns = (0..n_col)
+            .map(|i| Column::new(&format!("col_{}", i), 0, BigInt::zero(), max.clone()))
+            .collect();
+        Self { columns }
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.columns.iter().map(|el| el.name.as_str()).collect()
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|el| el.name == name)
+    }
+
+    pub fn check(&self, dataset: &[Vec<BigInt>]) -> anyhow::Result<()> {
+        dataset.iter().try_for_each(|row| {
+            if row.len() != self.len() {
+                return Err(anyhow!(
+                    "Row has {} values, schema has {} columns.",
+                    row.len(),
+                    self.len()
+                ));
+            }
+            row.iter()
+                .zip(self.columns.iter())
+                .try_for_each(|(el, column)| column.check(el))
+        })
+    }
+
+    pub fn table(&self, rows: &[Vec<BigInt>]) -> anyhow::Result<Table> {
+        Table::build(self, rows)
+    }
+
+    /// Hash of the encoded schema. Dataset signatures are made under it, so
+    /// signed data cannot be opened under a different schema.
+    pub fn digest(&self) -> [u8; 32] {
+        Sha256::digest(self.to_bytes()).into()
+    }
+
+    /// Checks the aggregate of `n_client` datasets can be opened, i.e. that
+    /// no column sums to more than [`MAX_VALUE`].
+    pub fn check_clients(&self, n_client: usize) -> anyhow::Result<()> {
+        match self
+            .columns
+            .iter()
+            .find(|el| &el.max * n_client > BigInt::from(MAX_VALUE))
+        {
+            Some(column) => Err(anyhow!(
+                "Column {} overflows {} with {} clients.",
+                column.name,
+                MAX_VALUE,
+                n_client
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Table {
+    pub schema: Schema,
+    pub rows: Vec<Vec<BigInt>>,
+}
+
+impl Table {
+    pub fn build(schema: &Schema, rows: &[Vec<BigInt>]) -> anyhow::Result<Self> {
+        if let Some(row) = rows.iter().find(|row| row.len() != schema.len()) {
+            return Err(anyhow!(
+                "Row has {} values, schema has {} columns.",
+                row.len(),
+                schema.len()
+            ));
+        }
+        Ok(Self {
+            schema: schema.clone(),
+            rows: rows.to_vec(),
+        })
+    }
+
+    pub fn column(&self, name: &str) -> Option<Vec<BigDecimal>> {
+        let i = self.schema.index(name)?;
+        let column = &self.schema.columns[i];
+        Some(self.rows.iter().map(|row| column.decode(&row[i])).collect())
+    }
+
+    pub fn decode(&self) -> Vec<Vec<BigDecimal>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(self.schema.columns.iter())
+                    .map(|(el, column)| column.decode(el))
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn fr(&self) -> Vec<Vec<Fr>> {
+        to_fr(&self.rows)
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.schema.names().join("\t"))?;
+        for row in self.decode() {
+            let row: Vec<String> = row.iter().map(|el| el.to_string()).collect();
+            writeln!(f, "{}", row.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_encode_decode() {
+        let column = Column::new("price", 2, BigInt::zero(), BigInt::from(10000));
+
+        let value = BigDecimal::from_str("12.34").unwrap();
+        let encoded = column.encode(&value).unwrap();
+        assert_eq!(encoded, BigInt::from(1234));
+        assert_eq!(column.decode(&encoded), value);
+
+        let value = BigDecimal::from_str("123.45").unwrap();
+        assert!(column.encode(&value).is_err());
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let column = Column::new("a", 0, BigInt::zero(), BigInt::from(1));
+        assert!(Schema::new(vec![column.clone(), column]).is_err());
+
+        let column = Column::new("a", 0, BigInt::from(2), BigInt::from(1));
+        assert!(Schema::new(vec![column]).is_err());
+
+        // Aggregates are opened by a bounded discrete log.
+        let schema = Schema::build(2, 8);
+        assert!(schema.check_clients(10_000).is_ok());
+        assert!(schema.check_clients(20_000).is_err());
+        assert!(Schema::unbounded(1).check_clients(256).is_ok());
+    }
+
+    #[test]
+    fn test_table() {
+        let schema = Schema::new(vec![
+            Column::new("a", 0, BigInt::zero(), BigInt::from(100)),
+            Column::new("b", 1, BigInt::zero(), BigInt::from(100)),
+        ])
+        .unwrap();
+
+        let values: Vec<BigInt> = (1..=4).map(BigInt::from).collect();
+        let rows: Vec<Vec<BigInt>> = values.chunks(2).map(|s| s.into()).collect();
+        let table = schema.table(&rows).unwrap();
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(
+            table.column("b").unwrap(),
+            vec![
+                BigDecimal::from_str("0.2").unwrap(),
+                BigDecimal::from_str("0.4").unwrap()
+            ]
+        );
+        assert!(table.column("c").is_none());
+
+        assert!(schema.table(&[values[..3].to_vec()]).is_err());
+    }
+}